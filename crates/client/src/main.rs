@@ -1,7 +1,11 @@
-use sprot::msg::{
-    model::PlayerId,
-    msg::{ChatMessage, Msg, StateData, VersionHandshakeInit},
-    MessageKind,
+use sprot::{
+    connection::{self, Connection},
+    msg::{
+        model::{PlayerId, ProtocolVersion},
+        msg::{ChatMessage, Msg, StateData, VersionHandshakeInit},
+        MessageKind,
+    },
+    state::GameState,
 };
 
 mod addr;
@@ -32,8 +36,9 @@ fn main() -> anyhow::Result<()> {
         )
         .context("could not create host")?;
 
-    // user data: 3 for v0.75, 4 for v0.76
-    host.connect(&server_addr, 1, 3).context("connect failed")?;
+    let mut connection = Connection::new(ProtocolVersion::V_0_75);
+    host.connect(&server_addr, 1, connection.connect_data())
+        .context("connect failed")?;
 
     host.compress_with_range_coder();
 
@@ -76,6 +81,7 @@ fn main() -> anyhow::Result<()> {
     // disconnect after all outgoing packets have been sent.
     // peer.disconnect_later(5);
     let mut my_player_id = PlayerId(255);
+    let mut state = GameState::new();
 
     loop {
         let e = host
@@ -99,8 +105,14 @@ fn main() -> anyhow::Result<()> {
 
                     println!("\t> {elapsed:?}");
 
+                    if let Err(err) = connection.server_message(&msg) {
+                        eprintln!("{err}");
+                    }
+
                     //println!(">> {:?}", msg);
 
+                    state.update(&msg);
+
                     match msg {
                         // ChatMessage
                         Msg::ChatMessage(ChatMessage {
@@ -230,9 +242,9 @@ fn main() -> anyhow::Result<()> {
                 }
                 EventKind::Disconnect { data } => {
                     println!(
-                        "connection closed, peer: {:?}, reason: {}",
+                        "connection closed, peer: {:?}, reason: {:?}",
                         e.peer_id(),
-                        data
+                        connection::disconnect_reason(*data)
                     );
                     std::process::exit(0);
                 }
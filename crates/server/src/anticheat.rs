@@ -0,0 +1,411 @@
+//! Plausibility checks of what clients report: movement, shots, hits and building.
+//!
+//! Failed checks are [`Violation`]s, which the server rejects and reports here. Their scores add
+//! up per player and decay over time, so only repeated violations lead to a [`Verdict`].
+
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use sprot::{
+    hitscan::{self, Target},
+    msg::model::{BlockPosition, HitKind, PlayerId, Position, WeaponKind},
+    weapon::WeaponStats,
+    world::World,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AntiCheatConfig {
+    /// Blocks a reported position may differ from the simulated one.
+    pub speed_tolerance: f32,
+    /// Differences of at least this many blocks count as a teleport.
+    pub teleport_distance: f32,
+    /// Fraction of the weapon delay a shot may come early, to account for jitter.
+    pub fire_rate_tolerance: f32,
+    /// Radians a hit may be off the view direction, to account for spread and latency.
+    pub max_hit_angle: f32,
+    /// Blocks between the eyes and a player hit with the spade.
+    pub melee_range: f32,
+    /// Blocks between the eyes and a block that is built or dug.
+    pub block_reach: f32,
+    pub warn_score: f32,
+    pub kick_score: f32,
+    /// Score that decays per second.
+    pub decay: f32,
+}
+
+impl Default for AntiCheatConfig {
+    fn default() -> Self {
+        Self {
+            speed_tolerance: 2.0,
+            teleport_distance: 10.0,
+            fire_rate_tolerance: 0.2,
+            max_hit_angle: 0.4,
+            melee_range: 3.0,
+            block_reach: 6.0,
+            warn_score: 10.0,
+            kick_score: 25.0,
+            decay: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Violation {
+    /// The player moved further than the simulation allows.
+    Speed {
+        distance: f32,
+    },
+    Teleport {
+        distance: f32,
+    },
+    /// More hits than the weapon fires within its delay.
+    FireRate {
+        interval: Duration,
+    },
+    /// A hit or a block shot at that is not in front of the shooter or behind a wall.
+    LineOfSight,
+    Reach {
+        distance: f32,
+    },
+    /// Building more blocks than the player has.
+    BlockStock {
+        needed: usize,
+        available: u8,
+    },
+}
+
+impl Violation {
+    pub const fn score(&self) -> f32 {
+        match self {
+            Self::Speed { .. } => 1.0,
+            Self::Teleport { .. } => 5.0,
+            Self::FireRate { .. } => 2.0,
+            Self::LineOfSight => 3.0,
+            Self::Reach { .. } | Self::BlockStock { .. } => 2.0,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Speed { distance } => write!(f, "moved {distance:.1} blocks too far"),
+            Self::Teleport { distance } => write!(f, "teleported {distance:.1} blocks"),
+            Self::FireRate { interval } => {
+                write!(f, "fired again after {} ms", interval.as_millis())
+            }
+            Self::LineOfSight => f.write_str("hit a player out of sight"),
+            Self::Reach { distance } => write!(f, "reached {distance:.1} blocks"),
+            Self::BlockStock { needed, available } => {
+                write!(f, "built {needed} blocks with {available} left")
+            }
+        }
+    }
+}
+
+/// What the server should do about a player after a violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Tolerated,
+    /// The score reached `warn_score`, the staff should have a look.
+    Warn,
+    Kick,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Suspect {
+    score: f32,
+    last_update: Instant,
+    last_shot: Option<Instant>,
+    /// Hits since `last_shot`, more than one for the pellets of a shotgun.
+    shot_hits: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AntiCheat {
+    config: AntiCheatConfig,
+    players: HashMap<PlayerId, Suspect>,
+}
+
+impl AntiCheat {
+    pub fn new(config: AntiCheatConfig) -> Self {
+        Self {
+            config,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Compares a position reported by `PositionData` to the simulated one.
+    pub fn check_movement(&self, reported: Position, simulated: Position) -> Result<(), Violation> {
+        let distance = reported.distance(simulated);
+
+        if distance >= self.config.teleport_distance {
+            Err(Violation::Teleport { distance })
+        } else if distance > self.config.speed_tolerance {
+            Err(Violation::Speed { distance })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that a hit with `weapon` does not come faster than the weapon fires.
+    ///
+    /// Returns whether the hit is the first of a shot, later hits are further pellets of a
+    /// shotgun shot.
+    pub fn check_fire_rate(
+        &mut self,
+        player_id: PlayerId,
+        weapon: WeaponKind,
+        now: Instant,
+    ) -> Result<bool, Violation> {
+        let stats = WeaponStats::of(weapon);
+        let min_interval =
+            Duration::from_secs_f32(stats.delay * (1.0 - self.config.fire_rate_tolerance));
+        let suspect = self.suspect(player_id, now);
+
+        match suspect.last_shot {
+            Some(last_shot) if now.saturating_duration_since(last_shot) < min_interval => {
+                suspect.shot_hits = suspect.shot_hits.saturating_add(1);
+                if suspect.shot_hits > stats.pellets {
+                    return Err(Violation::FireRate {
+                        interval: now.saturating_duration_since(last_shot),
+                    });
+                }
+
+                Ok(false)
+            }
+            _ => {
+                suspect.last_shot = Some(now);
+                suspect.shot_hits = 1;
+
+                Ok(true)
+            }
+        }
+    }
+
+    /// Checks that a shooter with the eyes at `origin` looking along `orientation` can hit
+    /// `kind` of `target`.
+    pub fn check_hit(
+        &self,
+        world: &World,
+        origin: Position,
+        orientation: Position,
+        target: &Target,
+        kind: HitKind,
+    ) -> Result<(), Violation> {
+        if kind == HitKind::Melee {
+            let distance = origin.distance(target.position);
+
+            return if distance > self.config.melee_range {
+                Err(Violation::Reach { distance })
+            } else {
+                Ok(())
+            };
+        }
+
+        if hitscan::is_hit_plausible(
+            world,
+            origin,
+            orientation,
+            target,
+            kind,
+            self.config.max_hit_angle,
+        ) {
+            Ok(())
+        } else {
+            Err(Violation::LineOfSight)
+        }
+    }
+
+    /// Checks that a block destroyed by a shot from the eyes at `origin` along `orientation` is
+    /// the first block in the line of fire, or next to it to account for latency.
+    pub fn check_shot_block(
+        &self,
+        world: &World,
+        origin: Position,
+        orientation: Position,
+        block: BlockPosition,
+    ) -> Result<(), Violation> {
+        let in_line = world
+            .raycast(origin, orientation, hitscan::MAX_RANGE)
+            .is_some_and(|hit| {
+                [
+                    hit.block.x - block.x,
+                    hit.block.y - block.y,
+                    hit.block.z - block.z,
+                ]
+                .iter()
+                .all(|offset| offset.abs() <= 1)
+            });
+
+        if in_line {
+            Ok(())
+        } else {
+            Err(Violation::LineOfSight)
+        }
+    }
+
+    /// Checks that a player with the eyes at `origin` can reach `block` and has the `needed`
+    /// blocks to build.
+    pub fn check_block(
+        &self,
+        origin: Position,
+        block: BlockPosition,
+        needed: usize,
+        available: u8,
+    ) -> Result<(), Violation> {
+        let distance = origin.distance(block.to_position());
+
+        if distance > self.config.block_reach {
+            Err(Violation::Reach { distance })
+        } else if needed > available as usize {
+            Err(Violation::BlockStock { needed, available })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Adds the score of `violation` to the player.
+    pub fn report(&mut self, player_id: PlayerId, violation: &Violation, now: Instant) -> Verdict {
+        let (warn_score, kick_score) = (self.config.warn_score, self.config.kick_score);
+        let suspect = self.suspect(player_id, now);
+
+        let previous = suspect.score;
+        suspect.score += violation.score();
+
+        if suspect.score >= kick_score {
+            Verdict::Kick
+        } else if previous < warn_score && suspect.score >= warn_score {
+            Verdict::Warn
+        } else {
+            Verdict::Tolerated
+        }
+    }
+
+    /// Forgets a player that left.
+    pub fn remove(&mut self, player_id: PlayerId) {
+        self.players.remove(&player_id);
+    }
+
+    /// The state of a player, with the score decayed until `now`.
+    fn suspect(&mut self, player_id: PlayerId, now: Instant) -> &mut Suspect {
+        let decay = self.config.decay;
+        let suspect = self.players.entry(player_id).or_insert(Suspect {
+            score: 0.0,
+            last_update: now,
+            last_shot: None,
+            shot_hits: 0,
+        });
+
+        let elapsed = now.saturating_duration_since(suspect.last_update);
+        suspect.score = (suspect.score - elapsed.as_secs_f32() * decay).max(0.0);
+        suspect.last_update = now;
+
+        suspect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::msg::model::Color;
+
+    use super::*;
+
+    #[test]
+    fn violations_add_up() {
+        let mut anticheat = AntiCheat::new(AntiCheatConfig::default());
+        let player = PlayerId(0);
+        let start = Instant::now();
+        let eyes = Position::new_xyz(10.5, 10.5, 40.0);
+
+        assert_eq!(
+            anticheat.check_movement(Position::new_xyz(11.0, 10.5, 40.0), eyes),
+            Ok(())
+        );
+        assert!(matches!(
+            anticheat.check_movement(Position::new_xyz(30.0, 10.5, 40.0), eyes),
+            Err(Violation::Teleport { .. })
+        ));
+
+        // All pellets of a shotgun shot may hit, but not the pellets of a second shot.
+        for pellet in 0..8 {
+            assert_eq!(
+                anticheat.check_fire_rate(player, WeaponKind::Shotgun, start),
+                Ok(pellet == 0)
+            );
+        }
+        assert!(anticheat
+            .check_fire_rate(player, WeaponKind::Shotgun, start)
+            .is_err());
+        assert_eq!(
+            anticheat.check_fire_rate(player, WeaponKind::Shotgun, start + Duration::from_secs(1)),
+            Ok(true)
+        );
+
+        let target = Target {
+            player_id: PlayerId(1),
+            position: Position::new_xyz(20.5, 10.5, 40.0),
+            orientation: Position::new_xyz(-1.0, 0.0, 0.0),
+            crouching: false,
+        };
+        let mut world = World::new();
+        let forward = Position::new_xyz(1.0, 0.0, 0.0);
+        let backward = Position::new_xyz(-1.0, 0.0, 0.0);
+        assert_eq!(
+            anticheat.check_hit(&world, eyes, forward, &target, HitKind::Head),
+            Ok(())
+        );
+        assert_eq!(
+            anticheat.check_hit(&world, eyes, backward, &target, HitKind::Head),
+            Err(Violation::LineOfSight)
+        );
+
+        let wall = BlockPosition::new_xyz(30, 10, 40);
+        world.set(wall, Some(Color::new_rgb(0, 0, 0)));
+        assert_eq!(
+            anticheat.check_shot_block(&world, eyes, forward, wall),
+            Ok(())
+        );
+        assert_eq!(
+            anticheat.check_shot_block(&world, eyes, backward, wall),
+            Err(Violation::LineOfSight)
+        );
+        assert_eq!(
+            anticheat.check_shot_block(&world, eyes, forward, BlockPosition::new_xyz(40, 10, 40)),
+            Err(Violation::LineOfSight)
+        );
+
+        assert!(matches!(
+            anticheat.check_block(eyes, wall, 1, 10),
+            Err(Violation::Reach { .. })
+        ));
+        assert_eq!(
+            anticheat.check_block(eyes, BlockPosition::new_xyz(11, 10, 41), 3, 2),
+            Err(Violation::BlockStock {
+                needed: 3,
+                available: 2
+            })
+        );
+
+        let verdicts: Vec<Verdict> = (0..9)
+            .map(|_| anticheat.report(player, &Violation::LineOfSight, start))
+            .collect();
+        assert_eq!(verdicts[2], Verdict::Tolerated);
+        assert_eq!(verdicts[3], Verdict::Warn);
+        assert_eq!(verdicts[4], Verdict::Tolerated);
+        assert_eq!(verdicts[8], Verdict::Kick);
+
+        // The score decays, 60 s at 0.5 per second clear the 27 points.
+        assert_eq!(
+            anticheat.report(
+                player,
+                &Violation::LineOfSight,
+                start + Duration::from_secs(60)
+            ),
+            Verdict::Tolerated
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Bans of IP addresses and ranges, persisted to a text file.
+//!
+//! Each line of the file is one ban, with tab separated fields:
+//! `range created expires admin reason`, where `expires` is `-` for permanent bans and the
+//! times are Unix timestamps in seconds.
+
+use std::{
+    fmt, fs, io,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// A single address or a CIDR range like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// The range of `prefix` leading bits of `address`, the prefix is capped at 32.
+    pub fn new(address: Ipv4Addr, prefix: u8) -> Self {
+        let prefix = prefix.min(32);
+
+        Self {
+            network: Ipv4Addr::from(u32::from(address) & Self::mask(prefix)),
+            prefix,
+        }
+    }
+
+    pub fn single(address: Ipv4Addr) -> Self {
+        Self::new(address, 32)
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & Self::mask(self.prefix) == u32::from(self.network)
+    }
+
+    const fn mask(prefix: u8) -> u32 {
+        match prefix {
+            0 => 0,
+            prefix => u32::MAX << (32 - prefix),
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix == 32 {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = BanError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BanError::InvalidRange(s.to_owned());
+
+        match s.split_once('/') {
+            Some((address, prefix)) => {
+                let address = address.parse().map_err(|_| invalid())?;
+                let prefix = prefix.parse().ok().filter(|prefix| *prefix <= 32);
+
+                prefix
+                    .map(|prefix| Self::new(address, prefix))
+                    .ok_or_else(invalid)
+            }
+            None => s.parse().map(Self::single).map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Parses a ban duration like `30m`, `12h` or `7d`, `perm` means forever.
+pub fn parse_duration(s: &str) -> Option<Option<Duration>> {
+    if s.eq_ignore_ascii_case("perm") {
+        return Some(None);
+    }
+
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount: u64 = s[..s.len() - 1].parse().ok()?;
+
+    Some(Some(Duration::from_secs(amount.checked_mul(unit)?)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    pub range: IpRange,
+    pub reason: String,
+    /// Name of the admin who banned the range.
+    pub admin: String,
+    pub created: u64,
+    /// `None` for permanent bans.
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn new(range: IpRange, duration: Option<Duration>, reason: String, admin: String) -> Self {
+        let created = unix_time();
+
+        Self {
+            range,
+            reason,
+            admin,
+            created,
+            expires: duration.map(|duration| created.saturating_add(duration.as_secs())),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn to_line(&self) -> String {
+        let expires = self
+            .expires
+            .map_or_else(|| "-".to_owned(), |expires| expires.to_string());
+        // Tabs and newlines would break the file format.
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.range,
+            self.created,
+            expires,
+            clean(&self.admin),
+            clean(&self.reason)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        let range = fields.next()?.parse().ok()?;
+        let created = fields.next()?.parse().ok()?;
+        let expires = match fields.next()? {
+            "-" => None,
+            expires => Some(expires.parse().ok()?),
+        };
+
+        Some(Self {
+            range,
+            created,
+            expires,
+            admin: fields.next()?.to_owned(),
+            reason: fields.next().unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum BanError {
+    InvalidRange(String),
+    /// A line of the ban file could not be parsed.
+    InvalidLine(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for BanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRange(range) => write!(f, "Invalid IP range {range}"),
+            Self::InvalidLine(line) => write!(f, "Invalid ban in line {line}"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for BanError {}
+
+impl From<io::Error> for BanError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BanList {
+    bans: Vec<Ban>,
+    /// The file the bans are saved to on every change.
+    path: Option<PathBuf>,
+}
+
+impl BanList {
+    /// A ban list that is not persisted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the bans from `path`, a missing file is an empty list.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BanError> {
+        let path = path.as_ref();
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let bans = data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Ban::from_line(line).ok_or(BanError::InvalidLine(i + 1)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            bans,
+            path: Some(path.to_owned()),
+        })
+    }
+
+    /// Writes the bans to the file they were loaded from.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data: String = self.bans.iter().map(|ban| ban.to_line() + "\n").collect();
+
+        fs::write(path, data)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Ban> {
+        self.bans.iter()
+    }
+
+    /// The ban that applies to `address` at `now`, if any.
+    pub fn find(&self, address: Ipv4Addr, now: u64) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| !ban.is_expired(now) && ban.range.contains(address))
+    }
+
+    /// Adds a ban, replacing an existing ban of the same range.
+    pub fn add(&mut self, ban: Ban) -> io::Result<()> {
+        self.bans.retain(|existing| existing.range != ban.range);
+        self.bans.push(ban);
+
+        self.save()
+    }
+
+    /// Removes the ban of `range`, returning it.
+    pub fn remove(&mut self, range: IpRange) -> io::Result<Option<Ban>> {
+        let Some(index) = self.bans.iter().position(|ban| ban.range == range) else {
+            return Ok(None);
+        };
+        let ban = self.bans.remove(index);
+        self.save()?;
+
+        Ok(Some(ban))
+    }
+
+    /// Drops expired bans.
+    pub fn prune(&mut self, now: u64) -> io::Result<()> {
+        let len = self.bans.len();
+        self.bans.retain(|ban| !ban.is_expired(now));
+
+        if self.bans.len() == len {
+            Ok(())
+        } else {
+            self.save()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_and_expiry() {
+        let range: IpRange = "10.1.2.3/16".parse().unwrap();
+        assert_eq!(range.to_string(), "10.1.0.0/16");
+        assert!(range.contains(Ipv4Addr::new(10, 1, 200, 7)));
+        assert!(!range.contains(Ipv4Addr::new(10, 2, 0, 1)));
+        assert!("10.1.2.3/33".parse::<IpRange>().is_err());
+        assert_eq!(parse_duration("2h"), Some(Some(Duration::from_secs(7200))));
+        assert_eq!(parse_duration("griefing"), None);
+
+        let path = std::env::temp_dir().join(format!("rspades-bans-{}.txt", std::process::id()));
+        let mut bans = BanList::load(&path).unwrap();
+
+        let ban = Ban::new(
+            range,
+            Some(Duration::from_secs(60)),
+            "griefing\tthe base".to_owned(),
+            "Admin".to_owned(),
+        );
+        bans.add(ban).unwrap();
+        bans.add(Ban::new(
+            IpRange::single(Ipv4Addr::new(1, 2, 3, 4)),
+            None,
+            String::new(),
+            "Admin".to_owned(),
+        ))
+        .unwrap();
+
+        let loaded = BanList::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.iter().count(), 2);
+
+        let now = unix_time();
+        let address = Ipv4Addr::new(10, 1, 0, 1);
+        assert_eq!(
+            loaded.find(address, now).map(|ban| ban.reason.as_str()),
+            Some("griefing the base")
+        );
+        assert!(loaded.find(address, now + 60).is_none());
+        assert!(loaded.find(Ipv4Addr::new(1, 2, 3, 4), u64::MAX).is_some());
+    }
+}
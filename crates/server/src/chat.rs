@@ -0,0 +1,348 @@
+//! Limits on the length and frequency of chat messages, as required for `ChatMessage`.
+//!
+//! Every violation counts towards muting and eventually kicking the player, violations are
+//! forgiven over time. Commands are limited separately, so a muted player can still use them.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use sprot::msg::model::PlayerId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatPolicyConfig {
+    /// Maximum number of characters of a message.
+    pub max_length: usize,
+    /// Time it takes to regain one message once the burst is used up.
+    pub interval: Duration,
+    /// Messages a player may send in a row before being limited by `interval`.
+    pub burst: u32,
+    /// How often a player may repeat the previous message.
+    pub max_repeats: usize,
+    /// Commands a player may send in a row, regained at one per `interval`.
+    pub command_burst: u32,
+    /// Violations after which a player is muted, `None` to never mute.
+    pub mute_after: Option<u32>,
+    pub mute_duration: Duration,
+    /// Violations after which a player is kicked, `None` to never kick.
+    pub kick_after: Option<u32>,
+    /// Time after which one violation is forgiven.
+    pub forgive_interval: Duration,
+    /// Words that are replaced by asterisks, matched case-insensitively.
+    pub filtered_words: Vec<String>,
+}
+
+impl Default for ChatPolicyConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 90,
+            interval: Duration::from_secs(2),
+            burst: 4,
+            max_repeats: 2,
+            command_burst: 4,
+            mute_after: Some(5),
+            mute_duration: Duration::from_secs(60),
+            kick_after: Some(10),
+            forgive_interval: Duration::from_secs(30),
+            filtered_words: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatOutcome {
+    /// The message may be sent, with filtered words replaced.
+    Allowed(String),
+    TooLong {
+        max_length: usize,
+    },
+    RateLimited,
+    Repeated,
+    Muted {
+        remaining: Duration,
+    },
+    /// The violation caused the player to be muted.
+    MutedNow {
+        duration: Duration,
+    },
+    /// The violation caused the player to be kicked.
+    Kick,
+}
+
+impl ChatOutcome {
+    /// The warning for the player, `None` if the message was allowed or the player is kicked.
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            Self::Allowed(_) | Self::Kick => None,
+            Self::TooLong { max_length } => Some(format!(
+                "Your message is too long, the limit is {max_length} characters"
+            )),
+            Self::RateLimited => Some("You are sending messages too fast".to_owned()),
+            Self::Repeated => Some("Do not repeat your messages".to_owned()),
+            Self::Muted { remaining } => Some(format!(
+                "You are muted for {} seconds",
+                remaining.as_secs().max(1)
+            )),
+            Self::MutedNow { duration } => Some(format!(
+                "You were muted for {} seconds for spamming",
+                duration.as_secs()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ChatState {
+    tokens: f32,
+    command_tokens: f32,
+    last_update: Instant,
+    last_message: Option<String>,
+    repeats: usize,
+    violations: f32,
+    muted_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatPolicy {
+    config: ChatPolicyConfig,
+    players: HashMap<PlayerId, ChatState>,
+}
+
+impl ChatPolicy {
+    pub fn new(config: ChatPolicyConfig) -> Self {
+        Self {
+            config,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Checks a message of `player_id`, updating the state of the player.
+    pub fn check(&mut self, player_id: PlayerId, message: &str, now: Instant) -> ChatOutcome {
+        let config = &self.config;
+        let state = Self::state(&mut self.players, config, player_id, now);
+
+        if let Some(muted_until) = state.muted_until {
+            if now < muted_until {
+                return ChatOutcome::Muted {
+                    remaining: muted_until - now,
+                };
+            }
+            state.muted_until = None;
+        }
+
+        let repeated = state
+            .last_message
+            .as_deref()
+            .is_some_and(|last| last.eq_ignore_ascii_case(message));
+        state.repeats = if repeated { state.repeats + 1 } else { 0 };
+        state.last_message = Some(message.to_owned());
+
+        let violation = if message.chars().count() > config.max_length {
+            ChatOutcome::TooLong {
+                max_length: config.max_length,
+            }
+        } else if state.tokens < 1.0 {
+            ChatOutcome::RateLimited
+        } else if state.repeats > config.max_repeats {
+            ChatOutcome::Repeated
+        } else {
+            state.tokens -= 1.0;
+
+            return ChatOutcome::Allowed(filter_words(message, &config.filtered_words));
+        };
+
+        Self::violation(config, state, violation, now)
+    }
+
+    /// Checks a command of `player_id`. Commands are neither muted nor filtered, but spamming
+    /// them counts as a violation.
+    pub fn check_command(&mut self, player_id: PlayerId, now: Instant) -> ChatOutcome {
+        let config = &self.config;
+        let state = Self::state(&mut self.players, config, player_id, now);
+
+        if state.command_tokens < 1.0 {
+            return Self::violation(config, state, ChatOutcome::RateLimited, now);
+        }
+        state.command_tokens -= 1.0;
+
+        ChatOutcome::Allowed(String::new())
+    }
+
+    /// The state of a player, with the tokens regained and the violations forgiven until `now`.
+    fn state<'a>(
+        players: &'a mut HashMap<PlayerId, ChatState>,
+        config: &ChatPolicyConfig,
+        player_id: PlayerId,
+        now: Instant,
+    ) -> &'a mut ChatState {
+        let state = players.entry(player_id).or_insert(ChatState {
+            tokens: config.burst as f32,
+            command_tokens: config.command_burst as f32,
+            last_update: now,
+            last_message: None,
+            repeats: 0,
+            violations: 0.0,
+            muted_until: None,
+        });
+
+        let elapsed = now.saturating_duration_since(state.last_update);
+        let regained = elapsed.as_secs_f32() / config.interval.as_secs_f32();
+        state.tokens = (state.tokens + regained).min(config.burst as f32);
+        state.command_tokens = (state.command_tokens + regained).min(config.command_burst as f32);
+        state.violations = (state.violations
+            - elapsed.as_secs_f32() / config.forgive_interval.as_secs_f32())
+        .max(0.0);
+        state.last_update = now;
+
+        state
+    }
+
+    /// Counts a violation, which may mute or kick the player.
+    fn violation(
+        config: &ChatPolicyConfig,
+        state: &mut ChatState,
+        violation: ChatOutcome,
+        now: Instant,
+    ) -> ChatOutcome {
+        let previous = state.violations;
+        state.violations += 1.0;
+
+        let reached =
+            |limit: Option<u32>| limit.is_some_and(|limit| state.violations >= limit as f32);
+        if reached(config.kick_after) {
+            ChatOutcome::Kick
+        } else if reached(config.mute_after)
+            && config.mute_after.is_some_and(|mute| previous < mute as f32)
+        {
+            state.muted_until = Some(now + config.mute_duration);
+
+            ChatOutcome::MutedNow {
+                duration: config.mute_duration,
+            }
+        } else {
+            violation
+        }
+    }
+
+    /// Forgets a player that left.
+    pub fn remove(&mut self, player_id: PlayerId) {
+        self.players.remove(&player_id);
+    }
+}
+
+/// Replaces the filtered words in `message` by asterisks.
+fn filter_words(message: &str, words: &[String]) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+            if !bare.is_empty()
+                && words
+                    .iter()
+                    .any(|filtered| filtered.eq_ignore_ascii_case(bare))
+            {
+                word.replace(bare, &"*".repeat(bare.chars().count()))
+            } else {
+                word.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escalation() {
+        let mut policy = ChatPolicy::new(ChatPolicyConfig {
+            burst: 2,
+            mute_after: Some(2),
+            kick_after: Some(3),
+            filtered_words: vec!["noob".to_owned()],
+            ..ChatPolicyConfig::default()
+        });
+        let player = PlayerId(1);
+        let start = Instant::now();
+
+        assert_eq!(
+            policy.check(player, "gg, Noob!", start),
+            ChatOutcome::Allowed("gg, ****!".to_owned())
+        );
+        assert_eq!(
+            policy.check(player, &"a".repeat(91), start),
+            ChatOutcome::TooLong { max_length: 90 }
+        );
+        assert_eq!(
+            policy.check(player, "hi", start),
+            ChatOutcome::Allowed("hi".to_owned())
+        );
+        assert_eq!(
+            policy.check(player, "hello", start),
+            ChatOutcome::MutedNow {
+                duration: Duration::from_secs(60)
+            }
+        );
+        assert!(matches!(
+            policy.check(player, "hello", start + Duration::from_secs(30)),
+            ChatOutcome::Muted { .. }
+        ));
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(
+            policy.check(player, "back", later),
+            ChatOutcome::Allowed("back".to_owned())
+        );
+        policy.check(player, "spam", later);
+        assert_eq!(
+            policy.check(player, "spam", later),
+            ChatOutcome::RateLimited
+        );
+        // The violations before the mute were forgiven meanwhile.
+        assert!(matches!(
+            policy.check(player, "spam", later),
+            ChatOutcome::MutedNow { .. }
+        ));
+    }
+
+    #[test]
+    fn forgiveness_and_commands() {
+        let mut policy = ChatPolicy::new(ChatPolicyConfig {
+            burst: 1,
+            command_burst: 2,
+            mute_after: Some(2),
+            kick_after: Some(3),
+            ..ChatPolicyConfig::default()
+        });
+        let player = PlayerId(1);
+        let start = Instant::now();
+
+        policy.check(player, "hi", start);
+        assert_eq!(
+            policy.check(player, "hi again", start),
+            ChatOutcome::RateLimited
+        );
+        // The violation is forgiven, so the next one does not mute.
+        let later = start + Duration::from_secs(30);
+        policy.check(player, "back", later);
+        assert_eq!(
+            policy.check(player, "still here", later),
+            ChatOutcome::RateLimited
+        );
+        assert!(matches!(
+            policy.check(player, "muted", later),
+            ChatOutcome::MutedNow { .. }
+        ));
+
+        // Muted players can use commands, but not spam them.
+        for _ in 0..2 {
+            assert!(matches!(
+                policy.check_command(player, later),
+                ChatOutcome::Allowed(_)
+            ));
+        }
+        assert_eq!(policy.check_command(player, later), ChatOutcome::Kick);
+    }
+}
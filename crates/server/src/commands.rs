@@ -0,0 +1,201 @@
+//! Chat commands: `/`-prefixed chat messages are parsed into an [`Invocation`] of one of the
+//! [`COMMANDS`], which are executed by the server.
+
+use std::fmt;
+
+/// What a player is allowed to do, higher levels include the lower ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Permission {
+    #[default]
+    Player,
+    Moderator,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub name: &'static str,
+    /// The arguments, `<required>` and `[optional]`.
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub permission: Permission,
+    /// Number of required arguments.
+    pub min_args: usize,
+}
+
+const fn command(
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    permission: Permission,
+    min_args: usize,
+) -> Command {
+    Command {
+        name,
+        usage,
+        description,
+        permission,
+        min_args,
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    command(
+        "help",
+        "[command]",
+        "Lists the commands",
+        Permission::Player,
+        0,
+    ),
+    command(
+        "login",
+        "<password>",
+        "Grants the rights of the password",
+        Permission::Player,
+        1,
+    ),
+    command(
+        "time",
+        "",
+        "Shows how long the map is running",
+        Permission::Player,
+        0,
+    ),
+    command(
+        "kick",
+        "<player> [reason]",
+        "Disconnects a player",
+        Permission::Moderator,
+        1,
+    ),
+    command(
+        "ban",
+        "<player> [duration] [reason]",
+        "Bans a player",
+        Permission::Moderator,
+        1,
+    ),
+    command(
+        "banip",
+        "<ip[/prefix]> [duration] [reason]",
+        "Bans an IP range",
+        Permission::Admin,
+        1,
+    ),
+    command("bans", "", "Lists the bans", Permission::Moderator, 0),
+    command(
+        "unban",
+        "<ip[/prefix]>",
+        "Removes a ban",
+        Permission::Admin,
+        1,
+    ),
+    command("map", "<name>", "Changes the map", Permission::Admin, 1),
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    UnknownCommand(String),
+    PermissionDenied(&'static Command),
+    Usage(&'static Command),
+    NoSuchPlayer(String),
+    /// A player name matches more than one player.
+    AmbiguousPlayer(String),
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCommand(name) => write!(f, "Unknown command /{name}, try /help"),
+            Self::PermissionDenied(command) => {
+                write!(f, "You are not allowed to use /{}", command.name)
+            }
+            Self::Usage(command) => write!(f, "Usage: /{} {}", command.name, command.usage),
+            Self::NoSuchPlayer(name) => write!(f, "No player named {name}"),
+            Self::AmbiguousPlayer(name) => write!(f, "More than one player matches {name}"),
+            Self::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation<'a> {
+    pub command: &'static Command,
+    pub args: Vec<&'a str>,
+}
+
+impl<'a> Invocation<'a> {
+    pub fn arg(&self, index: usize) -> Option<&'a str> {
+        self.args.get(index).copied()
+    }
+
+    /// The arguments from `index` on, joined by spaces, e.g. the reason of a kick.
+    pub fn rest(&self, index: usize) -> Option<String> {
+        self.args
+            .get(index..)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| rest.join(" "))
+    }
+
+    /// Checks whether a player with `permission` may run the command.
+    pub fn check(&self, permission: Permission) -> Result<(), CommandError> {
+        if permission < self.command.permission {
+            Err(CommandError::PermissionDenied(self.command))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Parses a chat message, returning `None` if it is not a command.
+pub fn parse(message: &str) -> Option<Result<Invocation<'_>, CommandError>> {
+    let mut words = message.strip_prefix('/')?.split_whitespace();
+    let name = words.next()?;
+
+    let Some(command) = find(name) else {
+        return Some(Err(CommandError::UnknownCommand(name.to_owned())));
+    };
+
+    let args: Vec<&str> = words.collect();
+    if args.len() < command.min_args {
+        return Some(Err(CommandError::Usage(command)));
+    }
+
+    Some(Ok(Invocation { command, args }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse("hello"), None);
+
+        let kick = parse("/KICK Deuce spawn camping").unwrap().unwrap();
+        assert_eq!(kick.command.name, "kick");
+        assert_eq!(kick.arg(0), Some("Deuce"));
+        assert_eq!(kick.rest(1).as_deref(), Some("spawn camping"));
+        assert_eq!(kick.rest(3), None);
+        assert!(kick.check(Permission::Player).is_err());
+        assert!(kick.check(Permission::Admin).is_ok());
+
+        assert_eq!(
+            parse("/map"),
+            Some(Err(CommandError::Usage(find("map").unwrap())))
+        );
+        assert_eq!(
+            parse("/fly"),
+            Some(Err(CommandError::UnknownCommand("fly".to_owned())))
+        );
+    }
+}
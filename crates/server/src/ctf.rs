@@ -0,0 +1,319 @@
+//! Capture the flag rules: players pick up the enemy intel by walking over it, capture it at
+//! their own base and restock at their base tent.
+//!
+//! The rules produce the objective messages, which are applied to the [`CTFModeState`] and
+//! have to be sent by the server.
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use sprot::{
+    gamemode::CTFModeState,
+    msg::{
+        model::{CaptureKind, GameMode, IntelLocation, PlayerId, Position, Team},
+        msg::{
+            IntelCapture, IntelDrop, IntelPickup, KillAction, MoveObject, Msg, Restock,
+            StateDataAddition,
+        },
+    },
+    state::Player,
+    world::World,
+};
+
+use crate::{
+    gamemode::{Context, GameModeRules},
+    spawn::{SpawnConfig, SpawnZone},
+};
+
+/// Blocks between the eyes of a player and an intel that is picked up.
+pub const INTEL_RANGE: f32 = 3.0;
+
+/// Blocks between the eyes of a player and the base to capture or restock.
+pub const BASE_RANGE: f32 = 3.0;
+
+/// Seconds between two restocks of a player.
+pub const RESTOCK_INTERVAL: f32 = 15.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CTFRules {
+    state: CTFModeState,
+    /// Where the intels are placed at the start and after a capture.
+    homes: [Position; 2],
+    /// Seconds until a player can restock again.
+    restocks: HashMap<PlayerId, f32>,
+    /// The spawn zones of blue and green, in which the intels and bases are placed after a map
+    /// change.
+    zones: [SpawnZone; 2],
+}
+
+impl CTFRules {
+    pub fn new(capture_limit: u8, intels: [Position; 2], bases: [Position; 2]) -> Self {
+        Self {
+            state: CTFModeState::new(capture_limit, intels.map(IntelLocation::Dropped), bases),
+            homes: intels,
+            restocks: HashMap::new(),
+            zones: SpawnConfig::default().zones,
+        }
+    }
+
+    /// A match with the intels and bases at safe spots in the spawn zones of their team, placed
+    /// again on every map change.
+    pub fn random(world: &World, zones: &[SpawnZone; 2], capture_limit: u8) -> Self {
+        let intels = zones.clone().map(|zone| zone.ground(world));
+        let bases = zones.clone().map(|zone| zone.ground(world));
+
+        Self {
+            zones: zones.clone(),
+            ..Self::new(capture_limit, intels, bases)
+        }
+    }
+
+    /// Checks the players against the intels and bases.
+    fn update<'a, I>(&mut self, dt: f32, players: I) -> Vec<Msg>
+    where
+        I: IntoIterator<Item = &'a Player>,
+    {
+        self.restocks.retain(|_, time| {
+            *time -= dt;
+            *time > 0.0
+        });
+
+        let mut msgs = Vec::new();
+        for player in players {
+            if !player.alive || player.is_spectator() {
+                continue;
+            }
+            let position = player.position.position;
+
+            if let Some(IntelLocation::Dropped(intel)) = self.state.intel(player.team.other()) {
+                if position.distance(intel) <= INTEL_RANGE {
+                    let pickup = Msg::IntelPickup(IntelPickup {
+                        player_id: player.id,
+                    });
+                    self.apply(&pickup, player.team);
+                    msgs.push(pickup);
+                }
+            }
+
+            let at_base = self
+                .state
+                .base(player.team)
+                .is_some_and(|base| position.distance(base) <= BASE_RANGE);
+            if !at_base {
+                continue;
+            }
+
+            if let Some(intel_team) = self.state.held_intel(player.id) {
+                msgs.extend(self.capture(player, intel_team));
+            }
+
+            if let Entry::Vacant(entry) = self.restocks.entry(player.id) {
+                entry.insert(RESTOCK_INTERVAL);
+                msgs.push(Msg::Restock(Restock {
+                    player_id: player.id,
+                }));
+            }
+        }
+
+        msgs
+    }
+
+    /// Drops the intel held by `player_id` at `position`.
+    fn drop_intel(&mut self, player_id: PlayerId, position: Position) -> Option<IntelDrop> {
+        self.state.held_intel(player_id)?;
+
+        let drop = IntelDrop {
+            player_id,
+            position,
+        };
+        self.state.update(&Msg::IntelDrop(drop), |_| None);
+
+        Some(drop)
+    }
+
+    /// Drops the intel held by `player_id` on the ground below the player.
+    fn drop_held_intel(&mut self, ctx: &Context, player_id: PlayerId) -> Vec<Msg> {
+        let Some(player) = ctx.player(player_id) else {
+            return Vec::new();
+        };
+
+        let position = player.position.position;
+        let ground = ctx.world.ground_level(position.x as i32, position.y as i32);
+        let position = Position::new_xyz(position.x, position.y, ground as f32);
+
+        self.drop_intel(player_id, position)
+            .map(Msg::IntelDrop)
+            .into_iter()
+            .collect()
+    }
+
+    /// Scores the intel of `intel_team` and returns it home. The winning capture restarts the
+    /// match.
+    fn capture(&mut self, player: &Player, intel_team: Team) -> Vec<Msg> {
+        let score = self.state.score(player.team).unwrap_or_default();
+        let kind = if score.saturating_add(1) >= self.state.capture_limit() {
+            CaptureKind::Winning
+        } else {
+            CaptureKind::Losing
+        };
+
+        let capture = Msg::IntelCapture(IntelCapture {
+            player_id: player.id,
+            kind,
+        });
+        self.apply(&capture, player.team);
+
+        let mut msgs = vec![capture];
+        let moved = if kind == CaptureKind::Winning {
+            self.state = CTFModeState::new(
+                self.state.capture_limit(),
+                self.homes.map(IntelLocation::Dropped),
+                [Team::BLUE, Team::GREEN].map(|team| self.state.base(team).unwrap_or_default()),
+            );
+
+            vec![Team::BLUE, Team::GREEN]
+        } else {
+            vec![intel_team]
+        };
+
+        for team in moved {
+            let Some(index) = team.index() else {
+                continue;
+            };
+
+            let move_object = Msg::MoveObject(MoveObject {
+                player_id: PlayerId(index as u8),
+                team,
+                position: self.homes[index],
+            });
+            self.apply(&move_object, player.team);
+            msgs.push(move_object);
+        }
+
+        msgs
+    }
+
+    fn apply(&mut self, msg: &Msg, team: Team) {
+        self.state.update(msg, |_| Some(team));
+    }
+}
+
+impl GameModeRules for CTFRules {
+    fn gamemode(&self) -> GameMode {
+        GameMode::CTF
+    }
+
+    fn state_addition(&self) -> StateDataAddition {
+        StateDataAddition::CTFState(self.state.to_state())
+    }
+
+    fn reset(&mut self, world: &World) {
+        *self = Self::random(world, &self.zones, self.state.capture_limit());
+    }
+
+    fn tick(&mut self, ctx: &Context, dt: f32) -> Vec<Msg> {
+        self.update(dt, ctx.players.iter().copied())
+    }
+
+    fn kill(&mut self, ctx: &Context, kill: &KillAction) -> Vec<Msg> {
+        self.drop_held_intel(ctx, kill.player_id)
+    }
+
+    fn leave(&mut self, ctx: &Context, player: &Player) -> Vec<Msg> {
+        self.restocks.remove(&player.id);
+
+        self.drop_held_intel(ctx, player.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::msg::model::WeaponKind;
+
+    use super::*;
+
+    #[test]
+    fn pickup_capture_and_win() {
+        let blue_intel = Position::new_xyz(10.5, 10.5, 60.0);
+        let green_intel = Position::new_xyz(500.5, 10.5, 60.0);
+        let blue_base = Position::new_xyz(20.5, 10.5, 60.0);
+        let mut rules = CTFRules::new(
+            2,
+            [blue_intel, green_intel],
+            [blue_base, Position::new_xyz(490.5, 10.5, 60.0)],
+        );
+
+        let mut player = Player::new(
+            PlayerId(3),
+            "Deuce".to_owned(),
+            Team::BLUE,
+            WeaponKind::Rifle,
+        );
+        player.spawn(green_intel);
+
+        assert_eq!(
+            rules.update(0.1, [&player]),
+            [Msg::IntelPickup(IntelPickup {
+                player_id: PlayerId(3)
+            })]
+        );
+        assert_eq!(rules.state.intel_holder(Team::GREEN), Some(PlayerId(3)));
+        assert!(rules.update(0.1, [&player]).is_empty());
+
+        player.position.position = blue_base;
+        let msgs = rules.update(0.1, [&player]);
+        assert_eq!(
+            msgs[..2],
+            [
+                Msg::IntelCapture(IntelCapture {
+                    player_id: PlayerId(3),
+                    kind: CaptureKind::Losing
+                }),
+                Msg::MoveObject(MoveObject {
+                    player_id: PlayerId(1),
+                    team: Team::GREEN,
+                    position: green_intel
+                }),
+            ]
+        );
+        assert_eq!(
+            msgs.last(),
+            Some(&Msg::Restock(Restock {
+                player_id: PlayerId(3)
+            }))
+        );
+        assert_eq!(rules.state.score(Team::BLUE), Some(1));
+
+        // Restocking has a cooldown.
+        assert!(rules.update(1.0, [&player]).is_empty());
+
+        player.position.position = green_intel;
+        rules.update(0.1, [&player]);
+        assert_eq!(
+            rules.drop_intel(PlayerId(3), blue_base),
+            Some(IntelDrop {
+                player_id: PlayerId(3),
+                position: blue_base
+            })
+        );
+
+        player.position.position = blue_base;
+        let msgs = rules.update(0.1, [&player]);
+        assert_eq!(
+            msgs[..2],
+            [
+                Msg::IntelPickup(IntelPickup {
+                    player_id: PlayerId(3)
+                }),
+                Msg::IntelCapture(IntelCapture {
+                    player_id: PlayerId(3),
+                    kind: CaptureKind::Winning
+                }),
+            ]
+        );
+        assert_eq!(rules.state.score(Team::BLUE), Some(0));
+        assert_eq!(
+            rules.state.intel(Team::GREEN),
+            Some(IntelLocation::Dropped(green_intel))
+        );
+    }
+}
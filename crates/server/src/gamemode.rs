@@ -0,0 +1,82 @@
+//! Game modes plug their rules into the server through [`GameModeRules`].
+//!
+//! On the wire every mode is CTF or TC, e.g. team deathmatch reports its scores as a CTF
+//! match. The hooks see the match through a [`Context`] and return the messages the server
+//! sends to all players, except `Restock`, which only goes to the restocked player after the
+//! server restocked them.
+
+use std::fmt;
+
+use sprot::{
+    msg::{
+        model::{ActionKind, BlockPosition, GameMode, PlayerId},
+        msg::{KillAction, Msg, StateDataAddition},
+    },
+    state::Player,
+    world::World,
+};
+
+/// What the hooks of a game mode see of the match.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub world: &'a World,
+    /// The players that joined the match.
+    pub players: &'a [&'a Player],
+}
+
+impl<'a> Context<'a> {
+    pub fn player(&self, player_id: PlayerId) -> Option<&'a Player> {
+        self.players
+            .iter()
+            .copied()
+            .find(|player| player.id == player_id)
+    }
+}
+
+pub trait GameModeRules: fmt::Debug {
+    /// What the mode reports itself as in the `StateData`.
+    fn gamemode(&self) -> GameMode;
+
+    /// The objective state for the `StateData`, matching [`GameModeRules::gamemode`].
+    fn state_addition(&self) -> StateDataAddition;
+
+    /// Restarts the mode on a new map.
+    fn reset(&mut self, world: &World);
+
+    /// Called on every tick of the server.
+    fn tick(&mut self, _ctx: &Context, _dt: f32) -> Vec<Msg> {
+        Vec::new()
+    }
+
+    /// Called after a player spawned.
+    fn spawn(&mut self, _ctx: &Context, _player: &Player) -> Vec<Msg> {
+        Vec::new()
+    }
+
+    /// Called before the `KillAction` is sent, while the victim is still alive.
+    fn kill(&mut self, _ctx: &Context, _kill: &KillAction) -> Vec<Msg> {
+        Vec::new()
+    }
+
+    /// Called before a player that joined the match leaves it.
+    fn leave(&mut self, _ctx: &Context, _player: &Player) -> Vec<Msg> {
+        Vec::new()
+    }
+
+    /// Decides whether `player` may build or destroy the block at `position`.
+    fn block_change(
+        &mut self,
+        _ctx: &Context,
+        _player: &Player,
+        _kind: ActionKind,
+        _position: BlockPosition,
+    ) -> bool {
+        true
+    }
+
+    /// Called after an `IntelPickup`, `IntelDrop` or `IntelCapture` returned by one of the
+    /// other hooks was sent. The messages returned by this hook do not trigger it again.
+    fn intel(&mut self, _ctx: &Context, _msg: &Msg) -> Vec<Msg> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,124 @@
+//! Per-address connection limits, rejecting connections with `IpLimitExceeded`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use sprot::msg::model::DisconnectReason;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Simultaneous connections from one address.
+    pub max_connections: usize,
+    /// Connection attempts from one address within `window`, including rejected ones.
+    pub max_attempts: usize,
+    pub window: Duration,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 3,
+            max_attempts: 5,
+            window: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimiter {
+    limits: ConnectionLimits,
+    connections: HashMap<Ipv4Addr, usize>,
+    attempts: HashMap<Ipv4Addr, VecDeque<Instant>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Records a connection attempt, counting it as connected if it is within the limits.
+    pub fn connect(&mut self, address: Ipv4Addr, now: Instant) -> Result<(), DisconnectReason> {
+        self.prune(now);
+
+        let attempts = self.attempts.entry(address).or_default();
+        attempts.push_back(now);
+        if attempts.len() > self.limits.max_attempts {
+            return Err(DisconnectReason::IpLimitExceeded);
+        }
+
+        let connections = self.connections.entry(address).or_default();
+        if *connections >= self.limits.max_connections {
+            return Err(DisconnectReason::IpLimitExceeded);
+        }
+        *connections += 1;
+
+        Ok(())
+    }
+
+    /// Releases a connection counted by `connect`.
+    pub fn disconnect(&mut self, address: Ipv4Addr) {
+        if let Some(connections) = self.connections.get_mut(&address) {
+            *connections = connections.saturating_sub(1);
+            if *connections == 0 {
+                self.connections.remove(&address);
+            }
+        }
+    }
+
+    /// Forgets attempts that are older than the window.
+    fn prune(&mut self, now: Instant) {
+        let window = self.limits.window;
+
+        self.attempts.retain(|_, attempts| {
+            while attempts
+                .front()
+                .is_some_and(|attempt| now.duration_since(*attempt) >= window)
+            {
+                attempts.pop_front();
+            }
+
+            !attempts.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_and_attempt_limits() {
+        let mut limiter = ConnectionLimiter::new(ConnectionLimits {
+            max_connections: 2,
+            max_attempts: 3,
+            window: Duration::from_secs(10),
+        });
+        let address = Ipv4Addr::new(10, 0, 0, 1);
+        let start = Instant::now();
+
+        assert_eq!(limiter.connect(address, start), Ok(()));
+        assert_eq!(limiter.connect(address, start), Ok(()));
+        assert_eq!(
+            limiter.connect(address, start),
+            Err(DisconnectReason::IpLimitExceeded)
+        );
+        assert_eq!(limiter.connect(Ipv4Addr::new(10, 0, 0, 2), start), Ok(()));
+
+        // Reconnecting in a loop is throttled even if the connection count is fine.
+        limiter.disconnect(address);
+        assert_eq!(
+            limiter.connect(address, start),
+            Err(DisconnectReason::IpLimitExceeded)
+        );
+        assert_eq!(
+            limiter.connect(address, start + Duration::from_secs(10)),
+            Ok(())
+        );
+    }
+}
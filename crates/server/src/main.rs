@@ -0,0 +1,153 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::Context;
+use sprot::world::World;
+
+mod anticheat;
+mod bans;
+mod chat;
+mod commands;
+mod ctf;
+mod gamemode;
+mod limits;
+mod map;
+mod net;
+mod scripting;
+mod server;
+mod spawn;
+mod tc;
+mod tdm;
+mod tick;
+
+use bans::{unix_time, BanList};
+use ctf::CTFRules;
+use gamemode::GameModeRules;
+use net::{Event, Host};
+use server::{Config, Output, Server};
+use tc::TCRules;
+use tdm::TeamDeathmatch;
+use tick::{Scheduler, TickRates};
+
+const DEFAULT_PORT: u16 = 32887;
+
+const BAN_FILE: &str = "bans.txt";
+
+/// Peers beyond `max_players`, so clients that are refused still receive the reason instead of
+/// ENet dropping them while they wait for their disconnect.
+const SPARE_PEERS: usize = 8;
+
+/// Ticks between two reports of the tick timing.
+const TIMING_REPORT_INTERVAL: u64 = 60 * 60;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let map_path = args
+        .next()
+        .context("usage: rspades-server <map.vxl> [port] [ctf|tc|tdm]")?;
+    let port = match args.next() {
+        Some(port) => port.parse().context("invalid port")?,
+        None => DEFAULT_PORT,
+    };
+    let gamemode = args.next();
+
+    let data = std::fs::read(&map_path).with_context(|| format!("could not read {map_path}"))?;
+    let world = World::from_vxl(&data).context("invalid map")?;
+
+    let config = Config {
+        admin_password: std::env::var("RSPADES_ADMIN_PASSWORD").ok(),
+        moderator_password: std::env::var("RSPADES_MODERATOR_PASSWORD").ok(),
+        ..Config::default()
+    };
+
+    let gamemode: Box<dyn GameModeRules> = match gamemode.as_deref() {
+        None | Some("ctf") => Box::new(CTFRules::random(
+            &world,
+            &config.spawn.zones,
+            config.capture_limit,
+        )),
+        Some("tc") => Box::new(TCRules::random(&world, tc::DEFAULT_TERRITORY_COUNT)),
+        Some("tdm") => Box::new(TeamDeathmatch::new(tdm::DEFAULT_KILL_LIMIT)),
+        Some(gamemode) => anyhow::bail!("unknown game mode {gamemode}"),
+    };
+
+    let mut host =
+        Host::listen(port, config.max_players + SPARE_PEERS).context("could not create host")?;
+
+    println!("listening on port {port}");
+
+    let mut bans = BanList::load(BAN_FILE).context("could not load the bans")?;
+    bans.prune(unix_time()).context("could not save the bans")?;
+    let mut server = Server::new(config, world)
+        .with_bans(bans)
+        .with_gamemode(gamemode);
+    let mut peers = HashMap::new();
+    let mut players = HashMap::new();
+
+    let mut scheduler = Scheduler::new(TickRates::default(), Instant::now());
+
+    loop {
+        match host.service(scheduler.timeout(Instant::now()))? {
+            Some(Event::Connect {
+                peer,
+                address,
+                data,
+            }) => match server.connect(address, data) {
+                Ok(player_id) => {
+                    peers.insert(peer, player_id);
+                    players.insert(player_id, peer);
+                }
+                Err(reason) => host.disconnect_later(peer, u32::from(reason.to_number()))?,
+            },
+            Some(Event::Disconnect { peer, .. }) => {
+                if let Some(player_id) = peers.remove(&peer) {
+                    players.remove(&player_id);
+                    server.disconnect(player_id);
+                }
+            }
+            Some(Event::Receive { peer, data }) => {
+                if let Some(&player_id) = peers.get(&peer) {
+                    server.receive(player_id, &data);
+                }
+            }
+            None => {}
+        }
+
+        while let Some(tick) = scheduler.poll(Instant::now()) {
+            let start = Instant::now();
+
+            server.tick(scheduler.dt());
+            if tick.world_update {
+                server.broadcast_world_update();
+            }
+
+            scheduler.record(start.elapsed());
+            if tick.number % TIMING_REPORT_INTERVAL == 0 && tick.number > 0 {
+                println!("tick timing: {}", scheduler.timing());
+            }
+        }
+
+        for output in server.take_output() {
+            match output {
+                Output::Packet {
+                    target,
+                    data,
+                    reliable,
+                } => {
+                    for player_id in server.recipients(target) {
+                        let Some(&peer) = players.get(&player_id) else {
+                            continue;
+                        };
+                        if let Err(err) = host.send(peer, &data, reliable) {
+                            eprintln!("sending to {player_id:?} failed: {err}");
+                        }
+                    }
+                }
+                Output::Disconnect { player_id, reason } => {
+                    if let Some(&peer) = players.get(&player_id) {
+                        host.disconnect_later(peer, u32::from(reason.to_number()))?;
+                    }
+                }
+            }
+        }
+    }
+}
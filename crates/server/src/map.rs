@@ -0,0 +1,171 @@
+//! Map transfer: the world is sent as zlib compressed VXL data, split into `MapChunk`s.
+//!
+//! The compressed data is cached per row of columns, so a block change only recompresses the
+//! rows it touches instead of the whole map.
+
+use std::io::{self, Write};
+
+use flate2::{write::DeflateEncoder, Compression};
+use sprot::{
+    msg::{model::BlockPosition, msg::MapChunk},
+    world::{World, MAP_Y},
+};
+
+/// Size of the compressed data in a single `MapChunk`.
+pub const CHUNK_SIZE: usize = 8192;
+
+/// Header of a zlib stream with the default compression.
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x9c];
+
+/// An empty final block with fixed Huffman codes, ending the deflate stream.
+const FINAL_BLOCK: [u8; 2] = [0x03, 0x00];
+
+/// Modulus of the Adler-32 checksum of zlib.
+const ADLER_BASE: u64 = 65521;
+
+/// A compressed row of columns, deflate blocks ending on a byte boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    compressed: Vec<u8>,
+    /// Adler-32 and length of the uncompressed data.
+    adler: u32,
+    len: usize,
+}
+
+impl Row {
+    fn compress(world: &World, y: i32) -> io::Result<Self> {
+        let mut data = Vec::new();
+        world.row_to_vxl(y, &mut data);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data)?;
+        // A sync flush ends the blocks on a byte boundary, so rows can be concatenated.
+        encoder.flush()?;
+
+        Ok(Self {
+            compressed: std::mem::take(encoder.get_mut()),
+            adler: adler32(&data),
+            len: data.len(),
+        })
+    }
+}
+
+/// The compressed world, recompressing only the rows changed since the last
+/// [`MapCache::compress`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapCache {
+    rows: Vec<Option<Row>>,
+}
+
+impl MapCache {
+    pub fn new() -> Self {
+        Self {
+            rows: vec![None; MAP_Y as usize],
+        }
+    }
+
+    /// Marks a changed block. The rows next to it change as well, as blocks that become
+    /// visible or hidden gain or lose their color in the VXL data.
+    pub fn invalidate(&mut self, position: BlockPosition) {
+        for y in position.y - 1..=position.y + 1 {
+            if let Some(row) = usize::try_from(y).ok().and_then(|y| self.rows.get_mut(y)) {
+                *row = None;
+            }
+        }
+    }
+
+    /// Forgets everything, e.g. after the world was replaced.
+    pub fn clear(&mut self) {
+        self.rows.fill(None);
+    }
+
+    /// The zlib compressed VXL data of `world`.
+    pub fn compress(&mut self, world: &World) -> io::Result<Vec<u8>> {
+        let mut data = ZLIB_HEADER.to_vec();
+        let mut adler = 1;
+
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            let row = match row {
+                Some(row) => row,
+                None => row.insert(Row::compress(world, y as i32)?),
+            };
+
+            data.extend_from_slice(&row.compressed);
+            adler = adler32_combine(adler, row.adler, row.len);
+        }
+
+        data.extend_from_slice(&FINAL_BLOCK);
+        data.extend_from_slice(&adler.to_be_bytes());
+
+        Ok(data)
+    }
+}
+
+impl Default for MapCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn chunks(data: &[u8]) -> impl Iterator<Item = MapChunk> + '_ {
+    data.chunks(CHUNK_SIZE).map(|chunk| MapChunk {
+        data: chunk.to_vec(),
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + u64::from(byte)) % ADLER_BASE;
+        (a, (b + a) % ADLER_BASE)
+    });
+
+    (b << 16 | a) as u32
+}
+
+/// The Adler-32 checksum of two concatenated pieces of data, from their checksums and the
+/// length of the second piece.
+fn adler32_combine(first: u32, second: u32, second_len: usize) -> u32 {
+    let (first, second) = (u64::from(first), u64::from(second));
+    let len = second_len as u64 % ADLER_BASE;
+
+    let a1 = first & 0xffff;
+    let a = (a1 + (second & 0xffff) + ADLER_BASE - 1) % ADLER_BASE;
+    let b = (len * a1 + (first >> 16) + (second >> 16) + ADLER_BASE - len) % ADLER_BASE;
+
+    (b << 16 | a) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+    use sprot::msg::model::Color;
+
+    use super::*;
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let mut vxl = Vec::new();
+        ZlibDecoder::new(data).read_to_end(&mut vxl).unwrap();
+        vxl
+    }
+
+    #[test]
+    fn rows_are_recompressed_after_changes() {
+        let mut world = World::new();
+        let mut cache = MapCache::new();
+        assert_eq!(decompress(&cache.compress(&world).unwrap()), world.to_vxl());
+
+        let position = BlockPosition::new_xyz(10, 20, 40);
+        world.set(position, Some(Color::new_rgb(1, 2, 3)));
+        cache.invalidate(position);
+        assert!(cache.rows[19..=21].iter().all(Option::is_none));
+        assert_eq!(decompress(&cache.compress(&world).unwrap()), world.to_vxl());
+
+        let data = b"Ace of Spades";
+        assert_eq!(
+            adler32_combine(adler32(&data[..4]), adler32(&data[4..]), data.len() - 4),
+            adler32(data)
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! A minimal ENet host on top of `enet-sys`.
+//!
+//! ENet-rs drops the data clients send with the connect, which carries their protocol version,
+//! so the server talks to ENet directly.
+
+use std::{fmt, mem::MaybeUninit, net::Ipv4Addr, slice, time::Duration};
+
+use enet_sys::{
+    _ENetEventType_ENET_EVENT_TYPE_CONNECT as CONNECT,
+    _ENetEventType_ENET_EVENT_TYPE_DISCONNECT as DISCONNECT,
+    _ENetEventType_ENET_EVENT_TYPE_RECEIVE as RECEIVE, _ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE,
+    enet_deinitialize, enet_host_compress_with_range_coder, enet_host_create, enet_host_destroy,
+    enet_host_service, enet_initialize, enet_packet_create, enet_packet_destroy,
+    enet_peer_disconnect_later, enet_peer_send, ENetAddress, ENetEvent, ENetHost, ENetPeer,
+};
+
+/// Index of a peer in the host, reused after the peer disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Connect {
+        peer: PeerId,
+        address: Ipv4Addr,
+        /// The protocol version for AoS clients.
+        data: u32,
+    },
+    Disconnect {
+        peer: PeerId,
+        data: u32,
+    },
+    Receive {
+        peer: PeerId,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error(&'static str);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ENet: {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug)]
+pub struct Host {
+    host: *mut ENetHost,
+}
+
+impl Host {
+    /// Listens on `port` for up to `peers` connections on a single channel, using the range
+    /// coder like AoS clients do.
+    pub fn listen(port: u16, peers: usize) -> Result<Self, Error> {
+        // SAFETY: Deinitialized when the host is dropped.
+        if unsafe { enet_initialize() } != 0 {
+            return Err(Error("could not initialize"));
+        }
+
+        let address = ENetAddress { host: 0, port };
+        // SAFETY: ENet copies the address.
+        let host = unsafe { enet_host_create(&address, peers, 1, 0, 0) };
+        if host.is_null() {
+            // SAFETY: Initialized above.
+            unsafe { enet_deinitialize() };
+            return Err(Error("could not create host"));
+        }
+
+        let host = Self { host };
+        // SAFETY: The host is valid until dropped.
+        if unsafe { enet_host_compress_with_range_coder(host.host) } != 0 {
+            return Err(Error("could not enable the range coder"));
+        }
+
+        Ok(host)
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn service(&mut self, timeout: Duration) -> Result<Option<Event>, Error> {
+        let timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let mut event = MaybeUninit::<ENetEvent>::zeroed();
+        // SAFETY: The host is valid until dropped and ENet fills in the event.
+        let result = unsafe { enet_host_service(self.host, event.as_mut_ptr(), timeout) };
+        if result < 0 {
+            return Err(Error("service failed"));
+        }
+        if result == 0 {
+            return Ok(None);
+        }
+
+        // SAFETY: Zeroed and filled in by ENet, the peer of an event is always set.
+        let event = unsafe { event.assume_init() };
+        let peer = self.peer_id(event.peer);
+
+        let event = match event.type_ {
+            CONNECT => {
+                // SAFETY: The peer is part of the host. The address is in network byte order.
+                let host = unsafe { (*event.peer).address.host };
+                Event::Connect {
+                    peer,
+                    address: Ipv4Addr::from(host.to_ne_bytes()),
+                    data: event.data,
+                }
+            }
+            DISCONNECT => Event::Disconnect {
+                peer,
+                data: event.data,
+            },
+            RECEIVE => {
+                // SAFETY: The packet of a receive event is owned by us and destroyed after
+                // copying its data.
+                let data = unsafe {
+                    let packet = &*event.packet;
+                    let data = slice::from_raw_parts(packet.data, packet.dataLength).to_vec();
+                    enet_packet_destroy(event.packet);
+                    data
+                };
+                Event::Receive { peer, data }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(event))
+    }
+
+    /// Queues `data` for `peer`, sequenced on the only channel.
+    pub fn send(&mut self, peer: PeerId, data: &[u8], reliable: bool) -> Result<(), Error> {
+        let peer = self.peer(peer)?;
+        let flags = if reliable {
+            _ENetPacketFlag_ENET_PACKET_FLAG_RELIABLE
+        } else {
+            0
+        };
+
+        // SAFETY: ENet copies the data.
+        let packet = unsafe { enet_packet_create(data.as_ptr().cast(), data.len(), flags) };
+        if packet.is_null() {
+            return Err(Error("could not create packet"));
+        }
+
+        // SAFETY: The peer is part of the host. ENet owns the packet once it was queued,
+        // otherwise it is destroyed here.
+        unsafe {
+            if enet_peer_send(peer, 0, packet) < 0 {
+                if (*packet).referenceCount == 0 {
+                    enet_packet_destroy(packet);
+                }
+                return Err(Error("could not send packet"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Disconnects `peer` after the queued packets were sent.
+    pub fn disconnect_later(&mut self, peer: PeerId, data: u32) -> Result<(), Error> {
+        let peer = self.peer(peer)?;
+        // SAFETY: The peer is part of the host.
+        unsafe { enet_peer_disconnect_later(peer, data) };
+
+        Ok(())
+    }
+
+    fn peer(&self, peer: PeerId) -> Result<*mut ENetPeer, Error> {
+        // SAFETY: The host is valid until dropped.
+        let host = unsafe { &*self.host };
+        if peer.0 >= host.peerCount {
+            return Err(Error("unknown peer"));
+        }
+
+        // SAFETY: In bounds of the peers of the host.
+        Ok(unsafe { host.peers.add(peer.0) })
+    }
+
+    fn peer_id(&self, peer: *mut ENetPeer) -> PeerId {
+        // SAFETY: The peers of events are part of the host.
+        let index = unsafe { peer.offset_from((*self.host).peers) };
+        PeerId(index as usize)
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        // SAFETY: The host is not used anymore, ENet was initialized when it was created.
+        unsafe {
+            enet_host_destroy(self.host);
+            enet_deinitialize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ptr, time::Instant};
+
+    use enet_sys::enet_host_connect;
+
+    use super::*;
+
+    const PORT: u16 = 43887;
+
+    impl Host {
+        /// A client host connecting to `port` on the loopback address.
+        fn connect(port: u16, data: u32) -> (Self, PeerId) {
+            // SAFETY: Deinitialized when the host is dropped.
+            assert_eq!(unsafe { enet_initialize() }, 0);
+            // SAFETY: A host without an address does not accept connections.
+            let host = unsafe { enet_host_create(ptr::null(), 1, 1, 0, 0) };
+            assert!(!host.is_null());
+            let host = Self { host };
+            // SAFETY: The host is valid until dropped.
+            assert_eq!(unsafe { enet_host_compress_with_range_coder(host.host) }, 0);
+
+            let address = ENetAddress {
+                host: u32::from_ne_bytes(Ipv4Addr::LOCALHOST.octets()),
+                port,
+            };
+            // SAFETY: ENet copies the address.
+            let peer = unsafe { enet_host_connect(host.host, &address, 1, data) };
+            assert!(!peer.is_null());
+            let peer = host.peer_id(peer);
+
+            (host, peer)
+        }
+    }
+
+    /// Services both hosts until `host` has an event, collecting the events of `other`.
+    fn poll(host: &mut Host, other: &mut Host, other_events: &mut Vec<Event>) -> Event {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(event) = other.service(Duration::ZERO).unwrap() {
+                other_events.push(event);
+            }
+            if let Some(event) = host.service(Duration::from_millis(10)).unwrap() {
+                return event;
+            }
+        }
+
+        panic!("no event within 5 seconds");
+    }
+
+    #[test]
+    fn loopback() {
+        let mut server = Host::listen(PORT, 2).unwrap();
+        let (mut client, server_peer) = Host::connect(PORT, 3);
+        let mut client_events = Vec::new();
+
+        let Event::Connect {
+            peer,
+            address,
+            data,
+        } = poll(&mut server, &mut client, &mut client_events)
+        else {
+            panic!("expected a connect");
+        };
+        assert_eq!((address, data), (Ipv4Addr::LOCALHOST, 3));
+
+        server.send(peer, b"state", true).unwrap();
+        let mut server_events = Vec::new();
+        let received = loop {
+            match poll(&mut client, &mut server, &mut server_events) {
+                Event::Connect { .. } => continue,
+                event => break event,
+            }
+        };
+        assert_eq!(
+            received,
+            Event::Receive {
+                peer: server_peer,
+                data: b"state".to_vec(),
+            }
+        );
+
+        client.send(server_peer, b"input", false).unwrap();
+        assert_eq!(
+            poll(&mut server, &mut client, &mut client_events),
+            Event::Receive {
+                peer,
+                data: b"input".to_vec(),
+            }
+        );
+
+        server.disconnect_later(peer, 7).unwrap();
+        assert_eq!(
+            poll(&mut client, &mut server, &mut server_events),
+            Event::Disconnect {
+                peer: server_peer,
+                data: 7,
+            }
+        );
+        assert_eq!(
+            poll(&mut server, &mut client, &mut client_events),
+            Event::Disconnect { peer, data: 0 }
+        );
+        assert_eq!(
+            server.send(PeerId(2), b"", true),
+            Err(Error("unknown peer"))
+        );
+    }
+}
@@ -0,0 +1,549 @@
+//! Server scripts written in [Rhai](https://rhai.rs): every `.rhai` file in the script
+//! directory is loaded and reloaded when it changes, without restarting the server.
+//!
+//! Scripts react to events by defining the functions of the [`Hook`]s, e.g.
+//! `fn on_kill(kill) { ... }`, which get the sprot messages and players as arguments. The
+//! hooks of a script are called with `this` bound to a map that keeps the state of the script
+//! until it is reloaded. Scripts act on the server through these functions:
+//!
+//! - `send_chat(message)` and `send_chat(player_id, message)` send a system message.
+//! - `spawn(player_id)` respawns a dead player right away.
+//! - `teleport(player_id, new_position(x, y, z))` moves a player.
+//! - `build_block(player_id, x, y, z)` and `destroy_block(player_id, x, y, z)` change a block
+//!   in the name of a player, builds have the color of the player.
+//! - `register_command(name, usage, permission, function)` adds a chat command, which calls
+//!   `function(player_id, args)` and replies with the returned string. The permission is
+//!   `"player"`, `"moderator"` or `"admin"`.
+//!
+//! The functions queue [`ScriptAction`]s, which are executed by the server after the hook
+//! returned.
+
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use sprot::{
+    msg::{
+        model::{BlockPosition, PlayerId, Position},
+        msg::{BlockAction, ChatMessage, KillAction},
+    },
+    state::Player,
+};
+
+use crate::commands::Permission;
+
+/// Seconds between two checks of the script directory for changes.
+pub const RELOAD_INTERVAL: f32 = 1.0;
+
+/// Operations a single call into a script may take, stops endless loops.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The functions scripts define to react to events of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// `on_join(player)`, after a player joined the match.
+    Join,
+    /// `on_leave(player)`, before a player that joined the match leaves it.
+    Leave,
+    /// `on_spawn(player)`, after a player spawned.
+    Spawn,
+    /// `on_kill(kill)`, with the `KillAction`, before it is sent.
+    Kill,
+    /// `on_chat(chat)`, with the `ChatMessage` of a player. Returning `false` drops it.
+    Chat,
+    /// `on_block(action)`, with the `BlockAction` of a player, block lines are passed as one
+    /// build for each block. Returning `false` denies the change.
+    Block,
+    /// `on_tick(dt)`, on every tick of the server.
+    Tick,
+}
+
+impl Hook {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Join => "on_join",
+            Self::Leave => "on_leave",
+            Self::Spawn => "on_spawn",
+            Self::Kill => "on_kill",
+            Self::Chat => "on_chat",
+            Self::Block => "on_block",
+            Self::Tick => "on_tick",
+        }
+    }
+}
+
+/// What a script asked the server to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptAction {
+    /// A system message to everybody, or to one player.
+    Chat {
+        target: Option<PlayerId>,
+        message: String,
+    },
+    Spawn(PlayerId),
+    Teleport {
+        player_id: PlayerId,
+        position: Position,
+    },
+    Build {
+        player_id: PlayerId,
+        position: BlockPosition,
+    },
+    Destroy {
+        player_id: PlayerId,
+        position: BlockPosition,
+    },
+}
+
+/// A chat command registered by a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCommand {
+    pub name: String,
+    pub usage: String,
+    pub permission: Permission,
+    /// The script function that runs the command.
+    function: String,
+}
+
+/// Filled by the functions the scripts call.
+#[derive(Debug, Default)]
+struct Queue {
+    actions: Vec<ScriptAction>,
+    commands: Vec<ScriptCommand>,
+}
+
+#[derive(Debug)]
+struct Script {
+    path: PathBuf,
+    modified: SystemTime,
+    ast: AST,
+    /// Bound to `this` in the hooks.
+    state: Dynamic,
+    commands: Vec<ScriptCommand>,
+}
+
+#[derive(Debug)]
+pub struct Scripts {
+    engine: Engine,
+    dir: PathBuf,
+    scripts: Vec<Script>,
+    queue: Rc<RefCell<Queue>>,
+    /// Seconds until the directory is checked for changes.
+    reload: f32,
+}
+
+impl Scripts {
+    /// Scripts from `dir`, which are loaded on the first tick.
+    pub fn new(dir: PathBuf) -> Self {
+        let queue = Rc::new(RefCell::new(Queue::default()));
+
+        Self {
+            engine: engine(&queue),
+            dir,
+            scripts: Vec::new(),
+            queue,
+            reload: 0.0,
+        }
+    }
+
+    /// Loads new and changed scripts and drops the removed ones. A missing directory has no
+    /// scripts.
+    pub fn reload(&mut self) {
+        let mut files: Vec<(PathBuf, SystemTime)> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "rhai" {
+                    return None;
+                }
+                let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+
+                Some((path, modified))
+            })
+            .collect();
+        files.sort();
+
+        self.scripts.retain(|script| {
+            let kept = files.contains(&(script.path.clone(), script.modified));
+            if !kept {
+                println!("unloaded script {}", script.path.display());
+            }
+            kept
+        });
+
+        for (path, modified) in files {
+            if self.scripts.iter().any(|script| script.path == path) {
+                continue;
+            }
+
+            match self.load(&path, modified) {
+                Ok(script) => {
+                    println!("loaded script {}", path.display());
+                    self.scripts.push(script);
+                }
+                Err(err) => eprintln!("could not load script {}: {err}", path.display()),
+            }
+        }
+    }
+
+    fn load(&self, path: &Path, modified: SystemTime) -> Result<Script, Box<EvalAltResult>> {
+        let ast = self.engine.compile_file(path.to_path_buf())?;
+        let result = self.engine.run_ast(&ast);
+        let commands = std::mem::take(&mut self.queue.borrow_mut().commands);
+        result?;
+
+        Ok(Script {
+            path: path.to_path_buf(),
+            modified,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+            commands,
+        })
+    }
+
+    /// Reloads the scripts every [`RELOAD_INTERVAL`] and calls [`Hook::Tick`].
+    pub fn tick(&mut self, dt: f32) {
+        self.reload -= dt;
+        if self.reload <= 0.0 {
+            self.reload = RELOAD_INTERVAL;
+            self.reload();
+        }
+
+        self.call(Hook::Tick, vec![Dynamic::from_float(f64::from(dt))]);
+    }
+
+    /// Calls `hook` in every script that defines it. Returns `false` if a script returned
+    /// `false`, failing scripts are logged and ignored.
+    pub fn call(&mut self, hook: Hook, args: Vec<Dynamic>) -> bool {
+        let mut allowed = true;
+
+        for index in 0..self.scripts.len() {
+            let script = &self.scripts[index];
+            let defined = script.ast.iter_functions().any(|function| {
+                function.name == hook.name() && function.params.len() == args.len()
+            });
+            if !defined {
+                continue;
+            }
+
+            match self.call_script(index, hook.name(), args.clone()) {
+                Ok(result) => allowed &= result.as_bool().unwrap_or(true),
+                Err(err) => eprintln!(
+                    "{} failed in {}: {err}",
+                    hook.name(),
+                    self.scripts[index].path.display()
+                ),
+            }
+        }
+
+        allowed
+    }
+
+    fn call_script(
+        &mut self,
+        index: usize,
+        name: &str,
+        args: Vec<Dynamic>,
+    ) -> Result<Dynamic, Box<EvalAltResult>> {
+        let script = &mut self.scripts[index];
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut script.state);
+        let result =
+            self.engine
+                .call_fn_with_options(options, &mut Scope::new(), &script.ast, name, args);
+
+        let commands = std::mem::take(&mut self.queue.borrow_mut().commands);
+        script.commands.extend(commands);
+
+        result
+    }
+
+    /// The commands of all scripts.
+    pub fn commands(&self) -> impl Iterator<Item = &ScriptCommand> {
+        self.scripts.iter().flat_map(|script| &script.commands)
+    }
+
+    pub fn command(&self, name: &str) -> Option<&ScriptCommand> {
+        self.commands()
+            .find(|command| command.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Runs the command `name` for `player_id`, returning the reply of the script.
+    pub fn run_command(
+        &mut self,
+        name: &str,
+        player_id: PlayerId,
+        args: Vec<String>,
+    ) -> Result<Option<String>, Box<EvalAltResult>> {
+        let Some((index, function)) =
+            self.scripts.iter().enumerate().find_map(|(index, script)| {
+                let command = script
+                    .commands
+                    .iter()
+                    .find(|command| command.name.eq_ignore_ascii_case(name))?;
+
+                Some((index, command.function.clone()))
+            })
+        else {
+            return Ok(None);
+        };
+
+        let args: rhai::Array = args.into_iter().map(Dynamic::from).collect();
+        let reply = self.call_script(
+            index,
+            &function,
+            vec![
+                Dynamic::from_int(INT::from(player_id.0)),
+                Dynamic::from_array(args),
+            ],
+        )?;
+
+        Ok(reply.into_string().ok())
+    }
+
+    /// Takes the actions the scripts queued since the last call.
+    pub fn take_actions(&mut self) -> Vec<ScriptAction> {
+        std::mem::take(&mut self.queue.borrow_mut().actions)
+    }
+}
+
+/// An engine with the sprot types and the server functions, which queue into `queue`.
+fn engine(queue: &Rc<RefCell<Queue>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| println!("[script] {text}"));
+
+    engine
+        .register_type_with_name::<Position>("Position")
+        .register_fn("new_position", |x: f64, y: f64, z: f64| {
+            Position::new_xyz(x as f32, y as f32, z as f32)
+        })
+        .register_get("x", |position: &mut Position| f64::from(position.x))
+        .register_get("y", |position: &mut Position| f64::from(position.y))
+        .register_get("z", |position: &mut Position| f64::from(position.z));
+
+    engine
+        .register_type_with_name::<BlockPosition>("BlockPosition")
+        .register_get("x", |position: &mut BlockPosition| INT::from(position.x))
+        .register_get("y", |position: &mut BlockPosition| INT::from(position.y))
+        .register_get("z", |position: &mut BlockPosition| INT::from(position.z));
+
+    engine
+        .register_type_with_name::<Player>("Player")
+        .register_get("id", |player: &mut Player| INT::from(player.id.0))
+        .register_get("name", |player: &mut Player| player.name.clone())
+        .register_get("team", |player: &mut Player| INT::from(player.team.0))
+        .register_get("alive", |player: &mut Player| player.alive)
+        .register_get("hp", |player: &mut Player| INT::from(player.hp))
+        .register_get("kills", |player: &mut Player| INT::from(player.kills))
+        .register_get("deaths", |player: &mut Player| INT::from(player.deaths))
+        .register_get("position", |player: &mut Player| player.position.position);
+
+    engine
+        .register_type_with_name::<KillAction>("KillAction")
+        .register_get("player_id", |kill: &mut KillAction| {
+            INT::from(kill.player_id.0)
+        })
+        .register_get("killer_id", |kill: &mut KillAction| {
+            INT::from(kill.killer_id.0)
+        })
+        .register_get("kind", |kill: &mut KillAction| format!("{:?}", kill.kind))
+        .register_get("respawn_time", |kill: &mut KillAction| {
+            INT::from(kill.respawn_time)
+        });
+
+    engine
+        .register_type_with_name::<ChatMessage>("ChatMessage")
+        .register_get("player_id", |chat: &mut ChatMessage| {
+            INT::from(chat.player_id.0)
+        })
+        .register_get("kind", |chat: &mut ChatMessage| format!("{:?}", chat.kind))
+        .register_get("message", |chat: &mut ChatMessage| chat.message.clone());
+
+    engine
+        .register_type_with_name::<BlockAction>("BlockAction")
+        .register_get("player_id", |action: &mut BlockAction| {
+            INT::from(action.player_id.0)
+        })
+        .register_get("kind", |action: &mut BlockAction| {
+            format!("{:?}", action.kind)
+        })
+        .register_get("position", |action: &mut BlockAction| action.position);
+
+    let push = |queue: &Rc<RefCell<Queue>>| {
+        let queue = Rc::clone(queue);
+        move |action: ScriptAction| queue.borrow_mut().actions.push(action)
+    };
+
+    let send = push(queue);
+    engine.register_fn("send_chat", move |message: &str| {
+        send(ScriptAction::Chat {
+            target: None,
+            message: message.to_owned(),
+        });
+    });
+    let send = push(queue);
+    engine.register_fn("send_chat", move |player_id: INT, message: &str| {
+        send(ScriptAction::Chat {
+            target: Some(player(player_id)?),
+            message: message.to_owned(),
+        });
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let send = push(queue);
+    engine.register_fn("spawn", move |player_id: INT| {
+        send(ScriptAction::Spawn(player(player_id)?));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let send = push(queue);
+    engine.register_fn("teleport", move |player_id: INT, position: Position| {
+        send(ScriptAction::Teleport {
+            player_id: player(player_id)?,
+            position,
+        });
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let send = push(queue);
+    engine.register_fn(
+        "build_block",
+        move |player_id: INT, x: INT, y: INT, z: INT| {
+            send(ScriptAction::Build {
+                player_id: player(player_id)?,
+                position: block(x, y, z)?,
+            });
+            Ok::<_, Box<EvalAltResult>>(())
+        },
+    );
+    let send = push(queue);
+    engine.register_fn(
+        "destroy_block",
+        move |player_id: INT, x: INT, y: INT, z: INT| {
+            send(ScriptAction::Destroy {
+                player_id: player(player_id)?,
+                position: block(x, y, z)?,
+            });
+            Ok::<_, Box<EvalAltResult>>(())
+        },
+    );
+
+    let queue = Rc::clone(queue);
+    engine.register_fn(
+        "register_command",
+        move |name: &str, usage: &str, permission: &str, function: &str| {
+            let permission = match permission {
+                "player" => Permission::Player,
+                "moderator" => Permission::Moderator,
+                "admin" => Permission::Admin,
+                _ => return Err(format!("unknown permission {permission}").into()),
+            };
+            queue.borrow_mut().commands.push(ScriptCommand {
+                name: name.trim_start_matches('/').to_owned(),
+                usage: usage.to_owned(),
+                permission,
+                function: function.to_owned(),
+            });
+
+            Ok::<_, Box<EvalAltResult>>(())
+        },
+    );
+
+    engine
+}
+
+fn player(player_id: INT) -> Result<PlayerId, Box<EvalAltResult>> {
+    u8::try_from(player_id)
+        .map(PlayerId)
+        .map_err(|_| format!("invalid player id {player_id}").into())
+}
+
+fn block(x: INT, y: INT, z: INT) -> Result<BlockPosition, Box<EvalAltResult>> {
+    match (i32::try_from(x), i32::try_from(y), i32::try_from(z)) {
+        (Ok(x), Ok(y), Ok(z)) => Ok(BlockPosition { x, y, z }),
+        _ => Err(format!("invalid block position {x} {y} {z}").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::msg::model::ChatKind;
+
+    use super::*;
+
+    #[test]
+    fn hooks_commands_and_reload() {
+        let dir = std::env::temp_dir().join(format!("rspades-scripts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("greet.rhai");
+        fs::write(
+            &path,
+            r#"
+                register_command("hello", "", "player", "hello");
+
+                fn hello(player_id, args) {
+                    `Hello #${player_id} ${args.len()}`
+                }
+
+                fn on_chat(chat) {
+                    this.count = (this.count ?? 0) + 1;
+                    send_chat(chat.player_id, `message ${this.count}`);
+                    !chat.message.contains("grief")
+                }
+            "#,
+        )
+        .unwrap();
+
+        let mut scripts = Scripts::new(dir.clone());
+        scripts.tick(0.1);
+        assert_eq!(
+            scripts.command("HELLO").map(|c| c.permission),
+            Some(Permission::Player)
+        );
+        assert_eq!(
+            scripts
+                .run_command("hello", PlayerId(4), vec!["a".to_owned()])
+                .unwrap(),
+            Some("Hello #4 1".to_owned())
+        );
+
+        let chat = |message: &str| {
+            vec![Dynamic::from(ChatMessage {
+                player_id: PlayerId(4),
+                kind: ChatKind::All,
+                message: message.to_owned(),
+            })]
+        };
+        assert!(scripts.call(Hook::Chat, chat("hi")));
+        assert!(!scripts.call(Hook::Chat, chat("lets grief")));
+        assert_eq!(
+            scripts.take_actions().last(),
+            Some(&ScriptAction::Chat {
+                target: Some(PlayerId(4)),
+                message: "message 2".to_owned()
+            })
+        );
+
+        // Changed scripts are loaded again, without the state and the old commands.
+        fs::write(&path, "fn on_chat(chat) { send_chat(\"reloaded\"); }").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        scripts.scripts[0].modified = modified - std::time::Duration::from_secs(1);
+        scripts.reload();
+        assert!(scripts.command("hello").is_none());
+        assert!(scripts.call(Hook::Chat, chat("hi")));
+        assert_eq!(
+            scripts.take_actions(),
+            [ScriptAction::Chat {
+                target: None,
+                message: "reloaded".to_owned()
+            }]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
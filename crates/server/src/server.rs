@@ -0,0 +1,2125 @@
+//! The game logic of the server, independent of the network.
+//!
+//! Players are identified by their slot ([`PlayerId`]). Everything the server wants to send is
+//! queued as [`Output`] and has to be delivered by the caller.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    net::Ipv4Addr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use sprot::{
+    connection::Connection,
+    grenade::{Explosion, Grenade},
+    hitscan,
+    loadout::LoadoutChange,
+    msg::{
+        model::{
+            ActionKind, BlockPosition, CaptureKind, ChatKind, Color, DisconnectReason, HitKind,
+            PlayerId, PlayerPosition, Position, ProtocolVersion, Team, ToolKind, WeaponKey,
+            WeaponKind,
+        },
+        msg::{
+            BlockAction, BlockLine, ChangeTeam, ChangeWeapon, ChatMessage, CreatePlayer,
+            ExisitingPlayer, GrenadePacket, HitPacket, InputData, KillAction, MapStart75, Message,
+            Msg, PositionData, SetColor, SetTool, StateData, StateDataAddition,
+            VersionHandshakeInit, WeaponInput, WeaponReload, WorldUpdate75, WorldUpdate76,
+        },
+    },
+    physics::PlayerPhysics,
+    slots::{SlotAllocator, DEFAULT_CAPACITY},
+    state::{Player, MAX_BLOCKS},
+    weapon::{Damage, DamageOutcome, WeaponStats},
+    world::{self, VoxelChange, World},
+};
+
+use crate::{
+    anticheat::{AntiCheat, AntiCheatConfig, Verdict, Violation},
+    bans::{self, unix_time, Ban, BanError, BanList, IpRange},
+    chat::{ChatOutcome, ChatPolicy, ChatPolicyConfig},
+    commands::{self, CommandError, Invocation, Permission, COMMANDS},
+    ctf::CTFRules,
+    gamemode::{Context, GameModeRules},
+    limits::{ConnectionLimiter, ConnectionLimits},
+    map::{self, MapCache},
+    scripting::{Hook, ScriptAction, Scripts},
+    spawn::SpawnConfig,
+};
+
+/// Maximum length of a player name.
+pub const MAX_NAME_LENGTH: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeamConfig {
+    pub name: String,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub name: String,
+    pub fog_color: Color,
+    pub teams: [TeamConfig; 2],
+    /// Captures to win the CTF match hosted unless another game mode is set.
+    pub capture_limit: u8,
+    /// Player slots, `WorldUpdate75` only covers the first 32.
+    pub max_players: usize,
+    pub connection_limits: ConnectionLimits,
+    pub chat_policy: ChatPolicyConfig,
+    pub anticheat: AntiCheatConfig,
+    /// Players a team may have more than the other, checked on joins and team changes.
+    pub max_team_difference: usize,
+    pub spawn: SpawnConfig,
+    /// Directory of the maps for `/map`.
+    pub map_dir: PathBuf,
+    /// Directory of the `.rhai` scripts, see [`crate::scripting`].
+    pub script_dir: PathBuf,
+    /// Password for `/login` to get the `Admin` permission.
+    pub admin_password: Option<String>,
+    pub moderator_password: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "rspades".to_owned(),
+            fog_color: Color::new_rgb(128, 232, 255),
+            teams: [
+                TeamConfig {
+                    name: "Blue".to_owned(),
+                    color: Color::new_rgb(0, 0, 255),
+                },
+                TeamConfig {
+                    name: "Green".to_owned(),
+                    color: Color::new_rgb(0, 255, 0),
+                },
+            ],
+            capture_limit: 10,
+            max_players: DEFAULT_CAPACITY,
+            connection_limits: ConnectionLimits::default(),
+            chat_policy: ChatPolicyConfig::default(),
+            anticheat: AntiCheatConfig::default(),
+            max_team_difference: 2,
+            spawn: SpawnConfig::default(),
+            map_dir: PathBuf::from("maps"),
+            script_dir: PathBuf::from("scripts"),
+            admin_password: None,
+            moderator_password: None,
+        }
+    }
+}
+
+/// Receivers of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Player(PlayerId),
+    All,
+    /// Everybody except the given player.
+    Others(PlayerId),
+    Team(Team),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Packet {
+        target: Target,
+        data: Vec<u8>,
+        reliable: bool,
+    },
+    Disconnect {
+        player_id: PlayerId,
+        reason: DisconnectReason,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Client {
+    player: Player,
+    connection: Connection,
+    address: Ipv4Addr,
+    permission: Permission,
+    physics: PlayerPhysics,
+    /// Seconds until a dead player respawns.
+    respawn: Option<f32>,
+    /// Seconds until the reload requested with `WeaponReload` is done.
+    reload: Option<f32>,
+    /// Seconds until the gun fires the next bullet.
+    cooldown: f32,
+}
+
+impl Client {
+    /// Spends the bullets fired while the primary fire is held, one every
+    /// [`WeaponStats::delay`] seconds like the client fires them, whether they hit or not.
+    fn fire(&mut self, dt: f32) {
+        let player = &mut self.player;
+        let stats = WeaponStats::of(player.weapon);
+        let firing = self.connection.is_joined()
+            && player.alive
+            && player.tool == ToolKind::Gun
+            && player.weapon_input.is_active(WeaponKey::Primary);
+
+        // Shells are loaded one by one, so shooting interrupts the reload of a shotgun.
+        if firing && stats.reload_per_shell {
+            self.reload = None;
+        }
+
+        self.cooldown -= dt;
+        if firing && self.reload.is_none() {
+            while self.cooldown <= 0.0 && player.clip_ammo > 0 {
+                player.clip_ammo -= 1;
+                self.cooldown += stats.delay;
+            }
+        }
+        self.cooldown = self.cooldown.max(0.0);
+    }
+}
+
+#[derive(Debug)]
+pub struct Server {
+    config: Config,
+    world: World,
+    /// The compressed map, invalidated where the world changes.
+    map: MapCache,
+    gamemode: Box<dyn GameModeRules>,
+    slots: SlotAllocator,
+    bans: BanList,
+    limiter: ConnectionLimiter,
+    chat_policy: ChatPolicy,
+    anticheat: AntiCheat,
+    scripts: Scripts,
+    clients: BTreeMap<PlayerId, Client>,
+    grenades: Vec<Grenade>,
+    /// Seconds since the map was loaded.
+    map_time: f32,
+    output: Vec<Output>,
+}
+
+impl Server {
+    pub fn new(config: Config, world: World) -> Self {
+        let gamemode = Box::new(CTFRules::random(
+            &world,
+            &config.spawn.zones,
+            config.capture_limit,
+        ));
+        let slots = SlotAllocator::new(config.max_players);
+        let limiter = ConnectionLimiter::new(config.connection_limits);
+        let chat_policy = ChatPolicy::new(config.chat_policy.clone());
+        let anticheat = AntiCheat::new(config.anticheat);
+        let scripts = Scripts::new(config.script_dir.clone());
+
+        Self {
+            config,
+            world,
+            map: MapCache::new(),
+            gamemode,
+            slots,
+            bans: BanList::new(),
+            limiter,
+            chat_policy,
+            anticheat,
+            scripts,
+            clients: BTreeMap::new(),
+            grenades: Vec::new(),
+            map_time: 0.0,
+            output: Vec::new(),
+        }
+    }
+
+    /// All players that joined the match.
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.clients
+            .values()
+            .filter(|client| client.connection.is_joined())
+            .map(|client| &client.player)
+    }
+
+    /// Replaces the game mode, which has to be set up for the current world.
+    pub fn with_gamemode(self, gamemode: Box<dyn GameModeRules>) -> Self {
+        Self { gamemode, ..self }
+    }
+
+    /// Replaces the bans, e.g. with the ones loaded from the ban file.
+    pub fn with_bans(self, bans: BanList) -> Self {
+        Self { bans, ..self }
+    }
+
+    /// Bans `range` and disconnects the players in it.
+    pub fn ban(
+        &mut self,
+        range: IpRange,
+        duration: Option<Duration>,
+        reason: String,
+        admin: String,
+    ) -> io::Result<()> {
+        let result = self.bans.add(Ban::new(range, duration, reason, admin));
+
+        for (id, client) in &self.clients {
+            if range.contains(client.address) {
+                self.output.push(Output::Disconnect {
+                    player_id: *id,
+                    reason: DisconnectReason::Banned,
+                });
+            }
+        }
+
+        result
+    }
+
+    pub fn unban(&mut self, range: IpRange) -> io::Result<Option<Ban>> {
+        self.bans.remove(range)
+    }
+
+    pub fn player(&self, player_id: PlayerId) -> Option<&Player> {
+        self.clients.get(&player_id).map(|client| &client.player)
+    }
+
+    /// Takes everything that has to be sent since the last call.
+    pub fn take_output(&mut self) -> Vec<Output> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// The connected players a packet for `target` is delivered to.
+    pub fn recipients(&self, target: Target) -> Vec<PlayerId> {
+        self.clients
+            .iter()
+            .filter(|(id, client)| match target {
+                Target::Player(player_id) => **id == player_id,
+                Target::All => client.connection.is_loaded(),
+                Target::Others(player_id) => **id != player_id && client.connection.is_loaded(),
+                Target::Team(team) => client.connection.is_joined() && client.player.team == team,
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Accepts a new connection from `address` with the ENet connect `data`, starting the map
+    /// transfer.
+    pub fn connect(&mut self, address: Ipv4Addr, data: u32) -> Result<PlayerId, DisconnectReason> {
+        if self.bans.find(address, unix_time()).is_some() {
+            return Err(DisconnectReason::Banned);
+        }
+
+        let connection = Connection::accept(data).map_err(|err| err.disconnect_reason())?;
+        self.limiter.connect(address, Instant::now())?;
+        let player_id = match self.slots.allocate() {
+            Ok(player_id) => player_id,
+            Err(reason) => {
+                self.limiter.disconnect(address);
+                return Err(reason);
+            }
+        };
+
+        // A placeholder until the player joins.
+        let mut player = Player::new(player_id, String::new(), Team::SPECTATOR, WeaponKind::Rifle);
+        player.alive = false;
+        self.clients.insert(
+            player_id,
+            Client {
+                player,
+                connection,
+                address,
+                permission: Permission::Player,
+                physics: PlayerPhysics::new(Position::default(), Position::default()),
+                respawn: None,
+                reload: None,
+                cooldown: 0.0,
+            },
+        );
+
+        // Clients answer the challenge with a `VersionHandshakeResponse`, a wrong answer closes
+        // the connection.
+        let init = VersionHandshakeInit {
+            challenge: rand::random(),
+        };
+        self.send_connection(player_id, Msg::VersionHandshakeInit(init));
+        self.send_map(player_id);
+        self.send_state(player_id);
+
+        Ok(player_id)
+    }
+
+    /// Removes a player after the connection was closed.
+    pub fn disconnect(&mut self, player_id: PlayerId) {
+        let joined = self
+            .players()
+            .find(|player| player.id == player_id)
+            .cloned();
+        if let Some(player) = joined {
+            self.script_hook(Hook::Leave, player);
+        }
+        self.run_gamemode(|gamemode, ctx| match ctx.player(player_id) {
+            Some(player) => gamemode.leave(ctx, player),
+            None => Vec::new(),
+        });
+        self.chat_policy.remove(player_id);
+        self.anticheat.remove(player_id);
+
+        let left = self.slots.release(player_id);
+        if let (Some(client), Some(left)) = (self.clients.remove(&player_id), left) {
+            self.limiter.disconnect(client.address);
+
+            if client.connection.is_joined() {
+                println!("{} disconnected", client.player.name);
+                self.send(Target::All, &left, true);
+            }
+        }
+    }
+
+    /// Handles a packet received from a player. Malformed packets are ignored.
+    pub fn receive(&mut self, player_id: PlayerId, data: &[u8]) {
+        match Msg::parse_client(data) {
+            Ok(msg) => self.handle(player_id, msg),
+            Err(err) => eprintln!("invalid packet from {player_id:?}: {err:?}"),
+        }
+    }
+
+    /// Advances respawn and reload timers, players and grenades by `dt` seconds.
+    pub fn tick(&mut self, dt: f32) {
+        self.map_time += dt;
+
+        let reloads: Vec<PlayerId> = self
+            .clients
+            .iter_mut()
+            .filter_map(|(id, client)| {
+                let reload = client.reload.as_mut()?;
+                *reload -= dt;
+
+                (*reload <= 0.0).then_some(*id)
+            })
+            .collect();
+
+        for player_id in reloads {
+            self.reload(player_id);
+        }
+
+        for client in self.clients.values_mut() {
+            client.fire(dt);
+        }
+
+        let respawns: Vec<PlayerId> = self
+            .clients
+            .iter_mut()
+            .filter_map(|(id, client)| {
+                let respawn = client.respawn.as_mut()?;
+                *respawn -= dt;
+
+                (*respawn <= 0.0).then_some(*id)
+            })
+            .collect();
+
+        for player_id in respawns {
+            self.spawn(player_id);
+        }
+
+        let mut landings = Vec::new();
+        for (id, client) in &mut self.clients {
+            if !client.connection.is_joined() || !client.player.alive {
+                continue;
+            }
+
+            if let Some(landing) = client.physics.step(&self.world, dt) {
+                landings.push((*id, client.physics.position, landing.damage()));
+            }
+            client.player.position.position = client.physics.position;
+        }
+
+        for (player_id, position, damage) in landings {
+            if damage > 0 {
+                self.apply_damage(Damage::fall(player_id, position, damage));
+            }
+        }
+
+        let mut explosions = Vec::new();
+        self.grenades
+            .retain_mut(|grenade| match grenade.step(&self.world, dt) {
+                (_, Some(explosion)) => {
+                    explosions.push(explosion);
+                    false
+                }
+                (_, None) => true,
+            });
+
+        for explosion in explosions {
+            self.explode(&explosion);
+        }
+
+        self.run_gamemode(|gamemode, ctx| gamemode.tick(ctx, dt));
+
+        self.scripts.tick(dt);
+        self.run_script_actions();
+    }
+
+    /// Calls `hook` in the scripts and runs the actions they queued. Returns `false` if a
+    /// script denied the event.
+    fn script_hook<T: Clone + 'static>(&mut self, hook: Hook, arg: T) -> bool {
+        let allowed = self.scripts.call(hook, vec![rhai::Dynamic::from(arg)]);
+        self.run_script_actions();
+
+        allowed
+    }
+
+    fn run_script_actions(&mut self) {
+        for action in self.scripts.take_actions() {
+            match action {
+                ScriptAction::Chat { target, message } => {
+                    self.system_message(target.map_or(Target::All, Target::Player), message);
+                }
+                ScriptAction::Spawn(player_id) => {
+                    let dead = self
+                        .players()
+                        .any(|player| player.id == player_id && !player.alive);
+                    if dead {
+                        self.spawn(player_id);
+                    }
+                }
+                ScriptAction::Teleport {
+                    player_id,
+                    position,
+                } => {
+                    let Some(client) = self.clients.get_mut(&player_id) else {
+                        continue;
+                    };
+                    client.player.position.position = position;
+                    client.physics.position = position;
+                    self.send(Target::Player(player_id), &PositionData { position }, true);
+                }
+                ScriptAction::Build {
+                    player_id,
+                    position,
+                } => self.script_block(player_id, ActionKind::Build, position),
+                ScriptAction::Destroy {
+                    player_id,
+                    position,
+                } => self.script_block(player_id, ActionKind::BSLDestroy, position),
+            }
+        }
+    }
+
+    /// Changes a block for a script, without the checks and costs of a player doing it.
+    fn script_block(&mut self, player_id: PlayerId, kind: ActionKind, position: BlockPosition) {
+        let Some(color) = self.player(player_id).map(|player| player.color) else {
+            return;
+        };
+
+        let action = BlockAction {
+            player_id,
+            kind,
+            position,
+        };
+        let changes = self.world.apply_block_action(&action, color);
+        if changes.is_empty() {
+            return;
+        }
+
+        let removals = self.world.collapse(&changes).removals;
+        self.invalidate_map(changes.iter().chain(&removals));
+        self.send(Target::All, &action, true);
+    }
+
+    /// Calls a hook of the game mode with the current state of the match.
+    fn call_gamemode<R, F>(&mut self, hook: F) -> R
+    where
+        F: FnOnce(&mut dyn GameModeRules, &Context) -> R,
+    {
+        let players: Vec<&Player> = self
+            .clients
+            .values()
+            .filter(|client| client.connection.is_joined())
+            .map(|client| &client.player)
+            .collect();
+        let ctx = Context {
+            world: &self.world,
+            players: &players,
+        };
+
+        hook(self.gamemode.as_mut(), &ctx)
+    }
+
+    /// Calls a hook of the game mode and sends the messages it returns.
+    fn run_gamemode<F>(&mut self, hook: F)
+    where
+        F: FnOnce(&mut dyn GameModeRules, &Context) -> Vec<Msg>,
+    {
+        let msgs = self.call_gamemode(hook);
+        self.send_gamemode(msgs, true);
+    }
+
+    /// Sends the messages of the game mode. Intel messages are passed to the intel hook if
+    /// `intel_hook` is set.
+    fn send_gamemode(&mut self, msgs: Vec<Msg>, intel_hook: bool) {
+        for msg in msgs {
+            let target = match &msg {
+                Msg::Restock(restock) => {
+                    if let Some(client) = self.clients.get_mut(&restock.player_id) {
+                        client.player.restock();
+                    }
+                    Target::Player(restock.player_id)
+                }
+                _ => {
+                    self.log_capture(&msg);
+                    Target::All
+                }
+            };
+            self.send_msg(target, &msg, true);
+
+            let intel = matches!(
+                msg,
+                Msg::IntelPickup(_) | Msg::IntelDrop(_) | Msg::IntelCapture(_)
+            );
+            if intel_hook && intel {
+                let msgs = self.call_gamemode(|gamemode, ctx| gamemode.intel(ctx, &msg));
+                self.send_gamemode(msgs, false);
+            }
+        }
+    }
+
+    /// Logs the captures of the objectives.
+    fn log_capture(&self, msg: &Msg) {
+        let (player_id, kind) = match msg {
+            Msg::IntelCapture(capture) => (capture.player_id, capture.kind),
+            Msg::TerritoryCapture(capture) => (capture.player_id, capture.kind),
+            _ => return,
+        };
+        let Some(player) = self.player(player_id) else {
+            return;
+        };
+
+        match (kind, self.gamemode.state_addition()) {
+            (CaptureKind::Winning, _) => {
+                let team = player
+                    .team
+                    .index()
+                    .map(|index| &self.config.teams[index].name);
+                println!(
+                    "{} won the match for {}",
+                    player.name,
+                    team.unwrap_or(&player.name)
+                );
+            }
+            (CaptureKind::Losing, StateDataAddition::CTFState(state)) => println!(
+                "{} scored, {}:{}",
+                player.name, state.team1_score, state.team2_score
+            ),
+            (CaptureKind::Losing, StateDataAddition::TCState(state)) => {
+                let owned = state
+                    .territory_data
+                    .iter()
+                    .filter(|territory| territory.owner_team == player.team)
+                    .count();
+                println!(
+                    "{} captured a territory, {owned} of {} are owned by the team",
+                    player.name, state.territory_count
+                );
+            }
+        }
+    }
+
+    /// Sends the positions of all players, unreliably, in the format of the protocol version of
+    /// each client.
+    pub fn broadcast_world_update(&mut self) {
+        let mut updates = BTreeMap::new();
+
+        for (id, client) in &self.clients {
+            if !client.connection.is_loaded() {
+                continue;
+            }
+
+            let version = client.connection.version();
+            let data = updates
+                .entry(version)
+                .or_insert_with(|| world_update(version, self.players()).to_bytes());
+
+            self.output.push(Output::Packet {
+                target: Target::Player(*id),
+                data: data.clone(),
+                reliable: false,
+            });
+        }
+    }
+
+    fn send<M: Message>(&mut self, target: Target, msg: &M, reliable: bool) {
+        self.output.push(Output::Packet {
+            target,
+            data: msg.to_bytes(),
+            reliable,
+        });
+    }
+
+    fn send_msg(&mut self, target: Target, msg: &Msg, reliable: bool) {
+        self.output.push(Output::Packet {
+            target,
+            data: msg.to_bytes(),
+            reliable,
+        });
+    }
+
+    /// Sends a message of the join sequence, advancing the connection of the player.
+    ///
+    /// Returns `false` if the message was not sent.
+    fn send_connection(&mut self, player_id: PlayerId, msg: Msg) -> bool {
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return false;
+        };
+
+        if let Err(err) = client.connection.server_message(&msg) {
+            eprintln!("could not send {:?} to {player_id:?}: {err}", msg.kind());
+            return false;
+        }
+
+        self.output.push(Output::Packet {
+            target: Target::Player(player_id),
+            data: msg.to_bytes(),
+            reliable: true,
+        });
+
+        true
+    }
+
+    fn invalidate_map<'a>(&mut self, changes: impl IntoIterator<Item = &'a VoxelChange>) {
+        for change in changes {
+            self.map.invalidate(change.position());
+        }
+    }
+
+    fn send_map(&mut self, player_id: PlayerId) {
+        let map = match self.map.compress(&self.world) {
+            Ok(map) => map,
+            Err(err) => {
+                eprintln!("could not compress map: {err}");
+                return;
+            }
+        };
+
+        let start = MapStart75 {
+            size: map.len() as u32,
+        };
+        let sent = self.send_connection(player_id, Msg::MapStart75(start))
+            && map::chunks(&map).all(|chunk| self.send_connection(player_id, Msg::MapChunk(chunk)));
+        if !sent {
+            eprintln!("aborted the map transfer to {player_id:?}");
+        }
+    }
+
+    /// Sends the `StateData` and the players that already joined.
+    fn send_state(&mut self, player_id: PlayerId) {
+        let [team1, team2] = &self.config.teams;
+        let state = StateData {
+            player_id,
+            fog_color: self.config.fog_color,
+            team1_color: team1.color,
+            team2_color: team2.color,
+            team1_name: team1.name.clone(),
+            team2_name: team2.name.clone(),
+            gamemode: self.gamemode.gamemode(),
+            addition: Some(self.gamemode.state_addition()),
+        };
+        self.send_connection(player_id, Msg::StateData(state));
+
+        let existing: Vec<ExisitingPlayer> = self
+            .players()
+            .map(|player| ExisitingPlayer {
+                player_id: player.id,
+                team: player.team,
+                weapon: player.weapon,
+                held_item: player.tool,
+                kills: player.kills,
+                color: player.color,
+                name: player.name.clone(),
+            })
+            .collect();
+
+        for player in existing {
+            self.send(Target::Player(player_id), &player, true);
+        }
+    }
+
+    fn handle(&mut self, player_id: PlayerId, msg: Msg) {
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+
+        match client.connection.client_message(&msg) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                eprintln!("{player_id:?}: {err}");
+                self.output.push(Output::Disconnect {
+                    player_id,
+                    reason: err.disconnect_reason(),
+                });
+                return;
+            }
+        }
+
+        let player = &mut client.player;
+        let physics = &mut client.physics;
+        match msg {
+            Msg::PositionData(data) => {
+                match self
+                    .anticheat
+                    .check_movement(data.position, physics.position)
+                {
+                    Ok(()) => {
+                        player.position.position = data.position;
+                        physics.position = data.position;
+                    }
+                    Err(violation) => {
+                        // Puts the player back where the simulation has them.
+                        let correction = PositionData {
+                            position: physics.position,
+                        };
+                        self.send(Target::Player(player_id), &correction, true);
+                        self.violation(player_id, violation);
+                    }
+                }
+            }
+            Msg::OrientationData(data) => {
+                player.position.orientation = data.position;
+                physics.set_orientation(data.position);
+            }
+            Msg::InputData(InputData { state, .. }) => {
+                physics.set_input(&self.world, state);
+                player.input = physics.input();
+                self.send(
+                    Target::Others(player_id),
+                    &InputData { player_id, state },
+                    false,
+                );
+            }
+            Msg::WeaponInput(WeaponInput { state, .. }) => {
+                player.weapon_input = state;
+                physics.set_weapon_input(state);
+                client.fire(0.0);
+                self.send(
+                    Target::Others(player_id),
+                    &WeaponInput { player_id, state },
+                    false,
+                );
+            }
+            Msg::SetTool(SetTool { kind, .. }) => {
+                player.tool = kind;
+                physics.set_tool(kind);
+                self.send(
+                    Target::Others(player_id),
+                    &SetTool { player_id, kind },
+                    true,
+                );
+            }
+            Msg::SetColor(SetColor { color, .. }) => {
+                player.color = color;
+                self.send(
+                    Target::Others(player_id),
+                    &SetColor { player_id, color },
+                    true,
+                );
+            }
+            Msg::WeaponReload(_) => {
+                let stats = WeaponStats::of(player.weapon);
+                if player.alive
+                    && client.reload.is_none()
+                    && player.clip_ammo < stats.clip
+                    && player.reserve_ammo > 0
+                {
+                    client.reload = Some(stats.full_reload_time(player.clip_ammo));
+                }
+            }
+            Msg::ChatMessage(chat) => self.chat(player_id, chat),
+            Msg::HitPacket(hit) => self.hit(player_id, hit),
+            Msg::GrenadePacket(grenade) => self.grenade(player_id, grenade),
+            Msg::BlockAction(action) => self.block_action(player_id, action),
+            Msg::BlockLine(line) => self.block_line(player_id, line),
+            Msg::ExisitingPlayer(existing) => self.join(player_id, existing),
+            Msg::ChangeTeam(ChangeTeam { team, .. }) => {
+                self.change_loadout(player_id, LoadoutChange::Team(team));
+            }
+            Msg::ChangeWeapon(ChangeWeapon { kind, .. }) => {
+                self.change_loadout(player_id, LoadoutChange::Weapon(kind));
+            }
+            _ => {}
+        }
+    }
+
+    /// Refills the clip once the reload time passed.
+    fn reload(&mut self, player_id: PlayerId) {
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+        client.reload = None;
+
+        let player = &mut client.player;
+        if !player.alive {
+            return;
+        }
+
+        let stats = WeaponStats::of(player.weapon);
+        let loaded = (stats.clip - player.clip_ammo.min(stats.clip)).min(player.reserve_ammo);
+        player.clip_ammo += loaded;
+        player.reserve_ammo -= loaded;
+
+        let reload = WeaponReload {
+            player_id,
+            clip_ammo: player.clip_ammo,
+            reserve_ammo: player.reserve_ammo,
+        };
+        self.send(Target::All, &reload, true);
+    }
+
+    fn join(&mut self, player_id: PlayerId, existing: ExisitingPlayer) {
+        let name = self.unique_name(&existing.name);
+        let team = match existing.team {
+            Team::BLUE | Team::GREEN => existing.team,
+            _ => Team::SPECTATOR,
+        };
+        // Players joining a full team play for the other one.
+        let team = match self.full_team(team, player_id) {
+            Some(name) => {
+                let message = format!("{name} is full");
+                self.system_message(Target::Player(player_id), message);
+                team.other()
+            }
+            None => team,
+        };
+
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+
+        let mut player = Player::new(player_id, name, team, existing.weapon);
+        player.color = existing.color;
+        client.player = player;
+
+        println!("{} joined", client.player.name);
+        let player = client.player.clone();
+        self.script_hook(Hook::Join, player);
+        self.spawn(player_id);
+    }
+
+    /// Carries out a team or weapon change as described in [`sprot::loadout`]. A living player
+    /// is killed and respawns with the new loadout after the change delay, a dead player keeps
+    /// waiting for the respawn. Team changes that would unbalance the teams are refused.
+    fn change_loadout(&mut self, player_id: PlayerId, change: LoadoutChange) {
+        let Some(player) = self.player(player_id) else {
+            return;
+        };
+        let change = match change {
+            LoadoutChange::Team(Team::BLUE | Team::GREEN) | LoadoutChange::Weapon(_) => change,
+            LoadoutChange::Team(_) => LoadoutChange::Team(Team::SPECTATOR),
+        };
+        let unchanged = match change {
+            LoadoutChange::Team(team) => player.team == team,
+            LoadoutChange::Weapon(weapon) => player.weapon == weapon,
+        };
+        if unchanged {
+            return;
+        }
+
+        if let LoadoutChange::Team(team) = change {
+            if let Some(name) = self.full_team(team, player_id) {
+                self.system_message(Target::Player(player_id), format!("{name} is full"));
+                return;
+            }
+        }
+
+        let alive = player.alive;
+        let respawn_time = self.config.spawn.change_respawn_time;
+        for msg in change.announcement(player_id, alive, respawn_time) {
+            match msg {
+                Msg::KillAction(kill) => self.kill(kill),
+                // Only clients that loaded the map get the relay, like any other message.
+                msg => self.send_msg(Target::All, &msg, true),
+            }
+        }
+
+        if let Some(client) = self.clients.get_mut(&player_id) {
+            match change {
+                LoadoutChange::Team(team) => client.player.team = team,
+                LoadoutChange::Weapon(weapon) => client.player.weapon = weapon,
+            }
+        }
+    }
+
+    /// The name of `team` if `player_id` joining it would put it more than
+    /// [`Config::max_team_difference`] players ahead of the other team.
+    fn full_team(&self, team: Team, player_id: PlayerId) -> Option<&str> {
+        let index = team.index()?;
+        let count = |team| {
+            self.players()
+                .filter(|player| player.team == team && player.id != player_id)
+                .count()
+        };
+
+        let full = count(team) >= count(team.other()) + self.config.max_team_difference;
+        full.then_some(self.config.teams[index].name.as_str())
+    }
+
+    /// Truncates the name and appends a number if it is already in use.
+    fn unique_name(&self, name: &str) -> String {
+        let name: String = name.trim().chars().take(MAX_NAME_LENGTH).collect();
+        let name = if name.is_empty() {
+            "Deuce".to_owned()
+        } else {
+            name
+        };
+
+        let taken = |name: &str| {
+            self.players()
+                .any(|player| player.name.eq_ignore_ascii_case(name))
+        };
+
+        let mut unique = name.clone();
+        let mut n = 1;
+        while taken(&unique) {
+            // The suffix replaces the end of a long name.
+            let suffix = n.to_string();
+            let base: String = name.chars().take(MAX_NAME_LENGTH - suffix.len()).collect();
+            unique = format!("{base}{suffix}");
+            n += 1;
+        }
+
+        unique
+    }
+
+    fn spawn(&mut self, player_id: PlayerId) {
+        let Some(team) = self.player(player_id).map(|player| player.team) else {
+            return;
+        };
+
+        let position = self.config.spawn.position(&self.world, team);
+
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+        client.respawn = None;
+        client.reload = None;
+        client.cooldown = 0.0;
+        client.player.spawn(position);
+        client.physics = PlayerPhysics::new(position, client.player.position.orientation);
+
+        let create = CreatePlayer {
+            player_id,
+            weapon: client.player.weapon,
+            team,
+            position,
+            name: client.player.name.clone(),
+        };
+        self.send(Target::All, &create, true);
+
+        self.run_gamemode(|gamemode, ctx| match ctx.player(player_id) {
+            Some(player) => gamemode.spawn(ctx, player),
+            None => Vec::new(),
+        });
+
+        if let Some(player) = self.player(player_id) {
+            self.script_hook(Hook::Spawn, player.clone());
+        }
+    }
+
+    fn chat(&mut self, player_id: PlayerId, chat: ChatMessage) {
+        let Some(player) = self.player(player_id) else {
+            return;
+        };
+
+        let target = match chat.kind {
+            ChatKind::All => Target::All,
+            ChatKind::Team => Target::Team(player.team),
+            // Only the server sends system messages.
+            ChatKind::System => return,
+        };
+        let name = player.name.clone();
+
+        if let Some(invocation) = commands::parse(&chat.message) {
+            let outcome = self.chat_policy.check_command(player_id, Instant::now());
+            if self.chat_outcome(player_id, outcome).is_none() {
+                return;
+            }
+
+            let reply = match invocation {
+                Err(CommandError::UnknownCommand(name))
+                    if self.scripts.command(&name).is_some() =>
+                {
+                    self.script_command(player_id, &name, &chat.message)
+                }
+                invocation => {
+                    invocation.and_then(|invocation| self.command(player_id, &invocation))
+                }
+            }
+            .unwrap_or_else(|err| Some(err.to_string()));
+
+            if let Some(reply) = reply {
+                self.system_message(Target::Player(player_id), reply);
+            }
+            return;
+        }
+
+        let outcome = self
+            .chat_policy
+            .check(player_id, &chat.message, Instant::now());
+        let Some(message) = self.chat_outcome(player_id, outcome) else {
+            return;
+        };
+
+        // Logged after the commands, so the password of a `/login` never ends up in the log.
+        println!("<{name}> {message}");
+
+        let chat = ChatMessage {
+            player_id,
+            message,
+            ..chat
+        };
+        if !self.script_hook(Hook::Chat, chat.clone()) {
+            return;
+        }
+        self.send(target, &chat, true);
+    }
+
+    /// Warns or kicks the player for a refused message, returns the message if it is allowed.
+    fn chat_outcome(&mut self, player_id: PlayerId, outcome: ChatOutcome) -> Option<String> {
+        match outcome {
+            ChatOutcome::Allowed(message) => return Some(message),
+            ChatOutcome::Kick => self.output.push(Output::Disconnect {
+                player_id,
+                reason: DisconnectReason::Kicked,
+            }),
+            outcome => {
+                if let Some(warning) = outcome.warning() {
+                    self.system_message(Target::Player(player_id), warning);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Sends a `ChatKind::System` message.
+    fn system_message(&mut self, target: Target, message: String) {
+        for player_id in self.recipients(target) {
+            let chat = ChatMessage {
+                player_id,
+                kind: ChatKind::System,
+                message: message.clone(),
+            };
+            self.send(Target::Player(player_id), &chat, true);
+        }
+    }
+
+    /// Runs a chat command, returning the reply to the player.
+    fn command(
+        &mut self,
+        player_id: PlayerId,
+        invocation: &Invocation,
+    ) -> Result<Option<String>, CommandError> {
+        let permission = self
+            .clients
+            .get(&player_id)
+            .map_or(Permission::Player, |client| client.permission);
+        invocation.check(permission)?;
+
+        match invocation.command.name {
+            "help" => self.help(permission, invocation.arg(0)).map(Some),
+            "login" => {
+                // The password is required, so unset passwords never match.
+                let password = invocation.arg(0);
+                let permission = if password == self.config.admin_password.as_deref() {
+                    Permission::Admin
+                } else if password == self.config.moderator_password.as_deref() {
+                    Permission::Moderator
+                } else {
+                    return Err(CommandError::Failed("Wrong password".to_owned()));
+                };
+
+                if let Some(client) = self.clients.get_mut(&player_id) {
+                    client.permission = permission;
+                }
+                Ok(Some(format!("Logged in as {permission:?}")))
+            }
+            "time" => {
+                let seconds = self.map_time as u32;
+                Ok(Some(format!(
+                    "The map is running for {}:{:02}",
+                    seconds / 60,
+                    seconds % 60
+                )))
+            }
+            "kick" => {
+                let target = self.find_player(invocation.arg(0).unwrap_or_default())?;
+                let name = self.player(target).map(|player| player.name.clone());
+                let message = match invocation.rest(1) {
+                    Some(reason) => format!("{} was kicked: {reason}", name.unwrap_or_default()),
+                    None => format!("{} was kicked", name.unwrap_or_default()),
+                };
+                println!("{message}");
+                self.system_message(Target::All, message);
+                self.output.push(Output::Disconnect {
+                    player_id: target,
+                    reason: DisconnectReason::Kicked,
+                });
+
+                Ok(None)
+            }
+            "ban" | "banip" => {
+                let arg = invocation.arg(0).unwrap_or_default();
+                let (range, name) = if invocation.command.name == "ban" {
+                    let target = self.find_player(arg)?;
+                    let client = &self.clients[&target];
+
+                    (IpRange::single(client.address), client.player.name.clone())
+                } else {
+                    let range: IpRange = arg
+                        .parse()
+                        .map_err(|err: BanError| CommandError::Failed(err.to_string()))?;
+
+                    (range, range.to_string())
+                };
+
+                // The duration is optional, anything else is the start of the reason.
+                let (duration, reason) = match invocation.arg(1).and_then(bans::parse_duration) {
+                    Some(duration) => (duration, invocation.rest(2)),
+                    None => (None, invocation.rest(1)),
+                };
+                let admin = self
+                    .player(player_id)
+                    .map(|player| player.name.clone())
+                    .unwrap_or_default();
+
+                let message = match &reason {
+                    Some(reason) => format!("{name} was banned: {reason}"),
+                    None => format!("{name} was banned"),
+                };
+                println!("{message}");
+                self.system_message(Target::All, message);
+
+                self.ban(range, duration, reason.unwrap_or_default(), admin)
+                    .map_err(|err| {
+                        CommandError::Failed(format!("Could not save the ban: {err}"))
+                    })?;
+
+                Ok(None)
+            }
+            "bans" => {
+                let now = unix_time();
+                let bans: Vec<String> = self
+                    .bans
+                    .iter()
+                    .filter(|ban| !ban.is_expired(now))
+                    .map(|ban| match ban.expires {
+                        Some(expires) => {
+                            format!("{} ({}m)", ban.range, (expires - now).div_ceil(60))
+                        }
+                        None => ban.range.to_string(),
+                    })
+                    .collect();
+
+                if bans.is_empty() {
+                    Ok(Some("No bans".to_owned()))
+                } else {
+                    Ok(Some(format!("Bans: {}", bans.join(", "))))
+                }
+            }
+            "unban" => {
+                let range: IpRange = invocation
+                    .arg(0)
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(|err: BanError| CommandError::Failed(err.to_string()))?;
+
+                match self.unban(range) {
+                    Ok(Some(_)) => Ok(Some(format!("Unbanned {range}"))),
+                    Ok(None) => Err(CommandError::Failed(format!("{range} is not banned"))),
+                    Err(err) => Err(CommandError::Failed(format!(
+                        "Could not save the bans: {err}"
+                    ))),
+                }
+            }
+            "map" => {
+                let name = invocation.arg(0).unwrap_or_default();
+                self.load_map(name)
+                    .map_err(|err| CommandError::Failed(format!("Could not load {name}: {err}")))?;
+                self.system_message(Target::All, format!("Changing the map to {name}"));
+
+                Ok(None)
+            }
+            _ => Err(CommandError::UnknownCommand(
+                invocation.command.name.to_owned(),
+            )),
+        }
+    }
+
+    /// Runs a chat command registered by a script.
+    fn script_command(
+        &mut self,
+        player_id: PlayerId,
+        name: &str,
+        message: &str,
+    ) -> Result<Option<String>, CommandError> {
+        let permission = self
+            .clients
+            .get(&player_id)
+            .map_or(Permission::Player, |client| client.permission);
+        if self
+            .scripts
+            .command(name)
+            .is_some_and(|command| permission < command.permission)
+        {
+            return Err(CommandError::Failed(format!(
+                "You are not allowed to use /{name}"
+            )));
+        }
+
+        let args = message
+            .split_whitespace()
+            .skip(1)
+            .map(str::to_owned)
+            .collect();
+        let reply = self.scripts.run_command(name, player_id, args);
+        self.run_script_actions();
+
+        reply.map_err(|err| {
+            eprintln!("/{name} failed: {err}");
+            CommandError::Failed(format!("/{name} failed"))
+        })
+    }
+
+    fn help(&self, permission: Permission, name: Option<&str>) -> Result<String, CommandError> {
+        if let Some(name) = name {
+            let name = name.trim_start_matches('/');
+            if let Some(command) = commands::find(name) {
+                return Ok(format!(
+                    "/{} {} - {}",
+                    command.name, command.usage, command.description
+                ));
+            }
+
+            let command = self
+                .scripts
+                .command(name)
+                .ok_or_else(|| CommandError::UnknownCommand(name.to_owned()))?;
+            return Ok(format!("/{} {}", command.name, command.usage));
+        }
+
+        let builtin = COMMANDS
+            .iter()
+            .filter(|command| command.permission <= permission)
+            .map(|command| command.name);
+        let scripted = self
+            .scripts
+            .commands()
+            .filter(|command| command.permission <= permission)
+            .map(|command| command.name.as_str());
+        let names: Vec<String> = builtin
+            .chain(scripted)
+            .map(|name| format!("/{name}"))
+            .collect();
+
+        Ok(format!("Commands: {}", names.join(" ")))
+    }
+
+    /// Finds a player by `#id` or by a unique prefix of the name.
+    fn find_player(&self, name: &str) -> Result<PlayerId, CommandError> {
+        if let Some(id) = name.strip_prefix('#').and_then(|id| id.parse().ok()) {
+            return self
+                .players()
+                .find(|player| player.id == PlayerId(id))
+                .map(|player| player.id)
+                .ok_or_else(|| CommandError::NoSuchPlayer(name.to_owned()));
+        }
+
+        let lowercase = name.to_lowercase();
+        let mut matches = self
+            .players()
+            .filter(|player| player.name.to_lowercase().starts_with(&lowercase));
+
+        match (matches.next(), matches.next()) {
+            (Some(player), None) => Ok(player.id),
+            (Some(_), Some(_)) => self
+                .players()
+                .find(|player| player.name.eq_ignore_ascii_case(name))
+                .map(|player| player.id)
+                .ok_or_else(|| CommandError::AmbiguousPlayer(name.to_owned())),
+            (None, _) => Err(CommandError::NoSuchPlayer(name.to_owned())),
+        }
+    }
+
+    /// Loads `name` from the map directory and sends it to all players.
+    fn load_map(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-".contains(c))
+        {
+            return Err("invalid map name".into());
+        }
+
+        let data = std::fs::read(self.config.map_dir.join(format!("{name}.vxl")))?;
+        self.change_map(World::from_vxl(&data)?);
+
+        Ok(())
+    }
+
+    /// Replaces the world, restarting the game mode and the join sequence of all players.
+    pub fn change_map(&mut self, world: World) {
+        self.gamemode.reset(&world);
+        self.world = world;
+        self.map.clear();
+        self.grenades.clear();
+        self.map_time = 0.0;
+
+        let ids: Vec<PlayerId> = self.clients.keys().copied().collect();
+        for player_id in ids {
+            if let Some(client) = self.clients.get_mut(&player_id) {
+                client.respawn = None;
+                client.player.alive = false;
+                client.connection.change_map();
+            }
+
+            self.send_map(player_id);
+            self.send_state(player_id);
+        }
+    }
+
+    fn hit(&mut self, player_id: PlayerId, hit: HitPacket) {
+        let (Some(shooter), Some(victim)) =
+            (self.combatant(player_id), self.combatant(hit.player_id))
+        else {
+            return;
+        };
+        let (shooter, victim_physics, victim) = (&shooter.player, &victim.physics, &victim.player);
+
+        if !shooter.alive || !victim.alive || shooter.team == victim.team {
+            return;
+        }
+
+        let target = hitscan::Target {
+            player_id: hit.player_id,
+            position: victim.position.position,
+            orientation: victim.position.orientation,
+            crouching: victim_physics.is_crouching(),
+        };
+        let PlayerPosition {
+            position: origin,
+            orientation,
+        } = shooter.position;
+        let weapon = shooter.weapon;
+
+        let checked = if hit.kind == HitKind::Melee {
+            Ok(())
+        } else {
+            let Some(fired) = self.shoot(player_id) else {
+                return;
+            };
+            fired
+        }
+        .and_then(|()| {
+            self.anticheat
+                .check_hit(&self.world, origin, orientation, &target, hit.kind)
+        });
+        if let Err(violation) = checked {
+            self.violation(player_id, violation);
+            return;
+        }
+
+        let damage = Damage::hit(player_id, origin, weapon, hit.player_id, hit.kind);
+        self.apply_damage(damage);
+    }
+
+    /// Checks the fire rate of a hit on a player or a block. Returns `None` if the clip is
+    /// empty and no bullet was fired within the delay of the weapon, such hits are ignored.
+    fn shoot(&mut self, player_id: PlayerId) -> Option<Result<(), Violation>> {
+        let client = self.clients.get(&player_id)?;
+        if client.player.clip_ammo == 0 && client.cooldown <= 0.0 {
+            return None;
+        }
+
+        let fired = self
+            .anticheat
+            .check_fire_rate(player_id, client.player.weapon, Instant::now());
+
+        Some(fired.map(|_| ()))
+    }
+
+    /// A joined player of blue or green, the only players that can damage and be damaged.
+    fn combatant(&self, player_id: PlayerId) -> Option<&Client> {
+        self.clients
+            .get(&player_id)
+            .filter(|client| client.connection.is_joined() && client.player.team.index().is_some())
+    }
+
+    /// Reports a failed anti-cheat check, warning the staff or kicking the player if the
+    /// player keeps failing them.
+    fn violation(&mut self, player_id: PlayerId, violation: Violation) {
+        let Some(name) = self.player(player_id).map(|player| player.name.clone()) else {
+            return;
+        };
+        eprintln!("{name} ({player_id:?}) {violation}");
+
+        match self.anticheat.report(player_id, &violation, Instant::now()) {
+            Verdict::Tolerated => {}
+            Verdict::Warn => {
+                let staff: Vec<PlayerId> = self
+                    .clients
+                    .iter()
+                    .filter(|(_, client)| client.permission >= Permission::Moderator)
+                    .map(|(id, _)| *id)
+                    .collect();
+
+                for id in staff {
+                    self.system_message(
+                        Target::Player(id),
+                        format!("{name} is suspected of cheating, last {violation}"),
+                    );
+                }
+            }
+            Verdict::Kick => {
+                println!("{name} was kicked for cheating");
+                self.output.push(Output::Disconnect {
+                    player_id,
+                    reason: DisconnectReason::Kicked,
+                });
+            }
+        }
+    }
+
+    fn apply_damage(&mut self, damage: Damage) {
+        let respawn_time = self.config.spawn.respawn_time;
+        let Some(victim) = self.clients.get_mut(&damage.victim) else {
+            return;
+        };
+
+        if !victim.player.alive {
+            return;
+        }
+
+        match damage.apply(victim.player.hp, respawn_time) {
+            DamageOutcome::Damaged(set_hp) => {
+                victim.player.hp = set_hp.hp;
+                self.send(Target::Player(damage.victim), &set_hp, true);
+            }
+            DamageOutcome::Killed(kill) => self.kill(kill),
+        }
+    }
+
+    fn kill(&mut self, kill: KillAction) {
+        self.run_gamemode(|gamemode, ctx| gamemode.kill(ctx, &kill));
+        self.script_hook(Hook::Kill, kill);
+
+        if let Some(victim) = self.clients.get_mut(&kill.player_id) {
+            victim.player.hp = 0;
+            victim.player.alive = false;
+            victim.player.deaths += 1;
+            victim.respawn = Some(f32::from(kill.respawn_time));
+        }
+
+        if kill.killer_id != kill.player_id {
+            if let Some(killer) = self.clients.get_mut(&kill.killer_id) {
+                killer.player.kills += 1;
+            }
+        }
+
+        self.send(Target::All, &kill, true);
+    }
+
+    fn grenade(&mut self, player_id: PlayerId, grenade: GrenadePacket) {
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+
+        if !client.player.alive || client.player.grenades == 0 {
+            return;
+        }
+        client.player.grenades -= 1;
+
+        let grenade = GrenadePacket {
+            player_id,
+            ..grenade
+        };
+        self.grenades.push(Grenade::from_packet(&grenade));
+        self.send(Target::Others(player_id), &grenade, true);
+    }
+
+    fn explode(&mut self, explosion: &Explosion) {
+        let thrower_team = self
+            .combatant(explosion.player_id)
+            .map(|client| client.player.team);
+
+        let damages: Vec<Damage> = thrower_team
+            .into_iter()
+            .flat_map(|team| {
+                self.clients
+                    .keys()
+                    .filter_map(|id| self.combatant(*id))
+                    .map(|client| &client.player)
+                    .filter(move |player| player.id == explosion.player_id || player.team != team)
+            })
+            .filter(|player| player.alive)
+            .filter_map(|player| {
+                let amount = explosion.damage(&self.world, player.position.position);
+
+                (amount > 0.0).then(|| {
+                    Damage::grenade(explosion.player_id, explosion.position, player.id, amount)
+                })
+            })
+            .collect();
+
+        for damage in damages {
+            self.apply_damage(damage);
+        }
+
+        let changes = explosion.destroy(&mut self.world);
+        self.invalidate_map(&changes);
+        self.send(Target::All, &explosion.block_action(), true);
+    }
+
+    fn block_action(&mut self, player_id: PlayerId, action: BlockAction) {
+        let allowed = self.call_gamemode(|gamemode, ctx| {
+            ctx.player(player_id).is_some_and(|player| {
+                gamemode.block_change(ctx, player, action.kind, action.position)
+            })
+        });
+        let action = BlockAction {
+            player_id,
+            ..action
+        };
+        if !allowed || !self.script_hook(Hook::Block, action) {
+            return;
+        }
+
+        let Some(player) = self.player(player_id) else {
+            return;
+        };
+
+        let allowed = player.alive
+            && match action.kind {
+                ActionKind::Build => player.tool == ToolKind::Block,
+                ActionKind::BSLDestroy => matches!(player.tool, ToolKind::Spade | ToolKind::Gun),
+                ActionKind::SRDestroy => player.tool == ToolKind::Spade,
+                // Explosions are simulated by the server.
+                ActionKind::GDestroy => false,
+            };
+        if !allowed {
+            return;
+        }
+
+        let PlayerPosition {
+            position: origin,
+            orientation,
+        } = player.position;
+        let checked = if player.tool == ToolKind::Gun {
+            // Guns destroy blocks from afar, along the line of fire.
+            let Some(fired) = self.shoot(player_id) else {
+                return;
+            };
+            fired.and_then(|()| {
+                self.anticheat
+                    .check_shot_block(&self.world, origin, orientation, action.position)
+            })
+        } else {
+            let needed = usize::from(action.kind == ActionKind::Build);
+            self.anticheat
+                .check_block(origin, action.position, needed, player.blocks)
+        };
+        if let Err(violation) = checked {
+            self.violation(player_id, violation);
+            return;
+        }
+
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+        let player = &mut client.player;
+        let mut changes = self.world.apply_block_action(&action, player.color);
+        if changes.is_empty() {
+            return;
+        }
+
+        match action.kind {
+            ActionKind::Build => player.blocks -= 1,
+            ActionKind::BSLDestroy if player.tool == ToolKind::Spade => {
+                player.blocks = (player.blocks + 1).min(MAX_BLOCKS);
+            }
+            _ => {}
+        }
+
+        changes.extend(self.world.collapse(&changes).removals);
+        self.invalidate_map(&changes);
+        self.send(Target::All, &action, true);
+    }
+
+    fn block_line(&mut self, player_id: PlayerId, line: BlockLine) {
+        let allowed = self.call_gamemode(|gamemode, ctx| {
+            ctx.player(player_id).is_some_and(|player| {
+                world::block_line(line.start, line.end)
+                    .into_iter()
+                    .all(|position| gamemode.block_change(ctx, player, ActionKind::Build, position))
+            })
+        });
+        let allowed = allowed
+            && world::block_line(line.start, line.end)
+                .into_iter()
+                .all(|position| {
+                    let action = BlockAction {
+                        player_id,
+                        kind: ActionKind::Build,
+                        position,
+                    };
+                    self.script_hook(Hook::Block, action)
+                });
+        if !allowed {
+            return;
+        }
+
+        let Some(client) = self.clients.get_mut(&player_id) else {
+            return;
+        };
+        let player = &mut client.player;
+
+        if !player.alive || player.tool != ToolKind::Block {
+            return;
+        }
+
+        let cost = world::block_line(line.start, line.end).len();
+        let checked = [line.start, line.end].into_iter().try_for_each(|end| {
+            self.anticheat
+                .check_block(player.position.position, end, cost, player.blocks)
+        });
+        if let Err(violation) = checked {
+            self.violation(player_id, violation);
+            return;
+        }
+
+        let line = BlockLine { player_id, ..line };
+        let changes = self.world.apply_block_line(&line, player.color);
+        if changes.is_empty() {
+            return;
+        }
+
+        player.blocks -= changes.len() as u8;
+        self.invalidate_map(&changes);
+        self.send(Target::All, &line, true);
+    }
+}
+
+/// Builds the `WorldUpdate` for clients of `version`.
+///
+/// 0.75 has a slot for each of the first 32 player ids, 0.76 lists the players that are alive.
+pub fn world_update<'a, I>(version: ProtocolVersion, players: I) -> Msg
+where
+    I: IntoIterator<Item = &'a Player>,
+{
+    let alive = players.into_iter().filter(|player| player.alive);
+
+    match version {
+        ProtocolVersion::V_0_75 => {
+            let mut player_positions = [PlayerPosition::default(); 32];
+            for player in alive {
+                if let Some(slot) = player_positions.get_mut(player.id.0 as usize) {
+                    *slot = player.position;
+                }
+            }
+
+            Msg::WorldUpdate75(Box::new(WorldUpdate75 { player_positions }))
+        }
+        ProtocolVersion::V_0_76 => Msg::WorldUpdate76(WorldUpdate76 {
+            player_positions: alive.map(|player| (player.id, player.position)).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::{
+        loadout::PendingChange,
+        msg::{
+            model,
+            msg::{PlayerLeft, VersionHandshakeResponse},
+        },
+    };
+
+    use super::*;
+    use crate::tdm::{self, TeamDeathmatch};
+
+    fn packets(server: &mut Server) -> Vec<(Target, Msg)> {
+        server
+            .take_output()
+            .into_iter()
+            .filter_map(|output| match output {
+                Output::Packet { target, data, .. } => {
+                    Msg::parse_server(&data).ok().map(|msg| (target, msg))
+                }
+                Output::Disconnect { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn connect_and_join() {
+        let mut server = Server::new(Config::default(), World::new());
+
+        let player_id = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        let received = packets(&mut server);
+
+        let Msg::VersionHandshakeInit(init) = received[0].1 else {
+            panic!("expected a handshake, received {:?}", received[0].1);
+        };
+        assert!(matches!(received[1].1, Msg::MapStart75(_)));
+        assert!(matches!(received.last(), Some((_, Msg::StateData(_)))));
+
+        let response = VersionHandshakeResponse {
+            challenge: init.challenge,
+        };
+        server.receive(player_id, &response.to_bytes());
+        assert_eq!(server.take_output(), []);
+
+        let join = ExisitingPlayer {
+            player_id: PlayerId(255),
+            team: Team::GREEN,
+            weapon: WeaponKind::Smg,
+            held_item: ToolKind::Gun,
+            kills: 0,
+            color: Color::new_rgb(1, 2, 3),
+            name: "Deuce".to_owned(),
+        };
+        server.receive(player_id, &join.to_bytes());
+
+        let received = packets(&mut server);
+        assert!(matches!(
+            &received[..],
+            [(
+                Target::All,
+                Msg::CreatePlayer(CreatePlayer {
+                    team: Team::GREEN,
+                    ..
+                })
+            )]
+        ));
+
+        let other = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        server.take_output();
+        assert_eq!(
+            server.connect(Ipv4Addr::LOCALHOST, 5),
+            Err(DisconnectReason::WrongProtocolVersion)
+        );
+        server.receive(other, &join.to_bytes());
+        assert_eq!(server.player(other).unwrap().name, "Deuce1");
+        server.take_output();
+
+        let long = ExisitingPlayer {
+            name: "Ünïcödé Deuce Ünïcödé".to_owned(),
+            ..join.clone()
+        };
+        let ids = [1, 2].map(|host| server.connect(Ipv4Addr::new(10, 0, 0, host), 3).unwrap());
+        for id in ids {
+            server.receive(id, &long.to_bytes());
+        }
+        assert_eq!(server.player(ids[0]).unwrap().name, "Ünïcödé Deuce Ü");
+        assert_eq!(server.player(ids[1]).unwrap().name, "Ünïcödé Deuce 1");
+        server.take_output();
+
+        server.disconnect(player_id);
+        assert_eq!(
+            packets(&mut server),
+            [(Target::All, Msg::PlayerLeft(PlayerLeft { player_id }))]
+        );
+    }
+
+    #[test]
+    fn version_handshake() {
+        let mut server = Server::new(Config::default(), World::new());
+
+        let player_id = server.connect(Ipv4Addr::LOCALHOST, 4).unwrap();
+        assert_eq!(
+            server.clients[&player_id].connection.version(),
+            ProtocolVersion::V_0_76
+        );
+        let received = packets(&mut server);
+        let Msg::VersionHandshakeInit(init) = received[0].1 else {
+            panic!("expected a handshake, received {:?}", received[0].1);
+        };
+
+        let response = VersionHandshakeResponse {
+            challenge: init.challenge.wrapping_add(1),
+        };
+        server.receive(player_id, &response.to_bytes());
+        assert!(matches!(
+            &server.take_output()[..],
+            [Output::Disconnect { player_id: id, .. }] if *id == player_id
+        ));
+    }
+
+    #[test]
+    fn only_joined_teams_fight() {
+        let mut server = Server::new(Config::default(), World::new());
+        let join = |team| ExisitingPlayer {
+            player_id: PlayerId(255),
+            team,
+            weapon: WeaponKind::Rifle,
+            held_item: ToolKind::Gun,
+            kills: 0,
+            color: Color::new_rgb(1, 2, 3),
+            name: "Deuce".to_owned(),
+        };
+
+        let connecting = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        assert!(!server.player(connecting).unwrap().alive);
+        assert!(server.combatant(connecting).is_none());
+
+        let spectator = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        server.receive(spectator, &join(Team::SPECTATOR).to_bytes());
+        assert!(server.combatant(spectator).is_none());
+
+        let green = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        server.receive(green, &join(Team::GREEN).to_bytes());
+        assert!(server.combatant(green).is_some());
+        server.take_output();
+
+        // Neither the connecting player nor the spectator can hit or be hit.
+        for (shooter, victim) in [(green, connecting), (connecting, green), (spectator, green)] {
+            let hit = HitPacket {
+                player_id: victim,
+                kind: HitKind::Melee,
+            };
+            server.receive(shooter, &hit.to_bytes());
+            assert!(packets(&mut server).is_empty());
+        }
+    }
+
+    #[test]
+    fn reload_takes_time() {
+        // Without a base to restock at.
+        let mut server = Server::new(Config::default(), World::new())
+            .with_gamemode(Box::new(TeamDeathmatch::new(tdm::DEFAULT_KILL_LIMIT)));
+        let player_id = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        let join = ExisitingPlayer {
+            player_id,
+            team: Team::BLUE,
+            weapon: WeaponKind::Rifle,
+            held_item: ToolKind::Gun,
+            kills: 0,
+            color: Color::new_rgb(1, 2, 3),
+            name: "Deuce".to_owned(),
+        };
+        server.receive(player_id, &join.to_bytes());
+        server.clients.get_mut(&player_id).unwrap().player.clip_ammo = 0;
+        server.take_output();
+
+        let reload = WeaponReload {
+            player_id,
+            clip_ammo: 0,
+            reserve_ammo: 0,
+        };
+        server.receive(player_id, &reload.to_bytes());
+        let stats = WeaponStats::of(WeaponKind::Rifle);
+        server.tick(stats.reload_time / 2.0);
+        assert!(!packets(&mut server)
+            .iter()
+            .any(|(_, msg)| matches!(msg, Msg::WeaponReload(_))));
+
+        server.tick(stats.reload_time / 2.0);
+        let reloaded = WeaponReload {
+            player_id,
+            clip_ammo: stats.clip,
+            reserve_ammo: stats.reserve - stats.clip,
+        };
+        assert!(packets(&mut server).contains(&(Target::All, Msg::WeaponReload(reloaded))));
+    }
+
+    #[test]
+    fn missed_shots_cost_ammo() {
+        let mut server = Server::new(Config::default(), World::new())
+            .with_gamemode(Box::new(TeamDeathmatch::new(tdm::DEFAULT_KILL_LIMIT)));
+        let player_id = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        let join = ExisitingPlayer {
+            player_id,
+            team: Team::BLUE,
+            weapon: WeaponKind::Rifle,
+            held_item: ToolKind::Gun,
+            kills: 0,
+            color: Color::new_rgb(1, 2, 3),
+            name: "Deuce".to_owned(),
+        };
+        server.receive(player_id, &join.to_bytes());
+        server.take_output();
+
+        // Small steps, large ones hurt the falling player.
+        let run = |server: &mut Server, seconds: f32| {
+            for _ in 0..(seconds * 60.0).round() as u32 {
+                server.tick(1.0 / 60.0);
+            }
+        };
+
+        // Three shots into the air, at 0, 0.5 and 1 seconds.
+        let stats = WeaponStats::of(WeaponKind::Rifle);
+        let fire = |primary: bool| WeaponInput {
+            player_id,
+            state: model::WeaponInput(u8::from(primary)),
+        };
+        server.receive(player_id, &fire(true).to_bytes());
+        run(&mut server, stats.delay * 2.5);
+        server.receive(player_id, &fire(false).to_bytes());
+        run(&mut server, stats.delay * 4.0);
+        assert_eq!(server.player(player_id).unwrap().clip_ammo, stats.clip - 3);
+
+        let reload = WeaponReload {
+            player_id,
+            clip_ammo: 0,
+            reserve_ammo: 0,
+        };
+        server.receive(player_id, &reload.to_bytes());
+        run(&mut server, stats.reload_time + 0.1);
+        let reloaded = WeaponReload {
+            player_id,
+            clip_ammo: stats.clip,
+            reserve_ammo: stats.reserve - 3,
+        };
+        assert!(packets(&mut server).contains(&(Target::All, Msg::WeaponReload(reloaded))));
+
+        // Holding the trigger empties the clip, hits after that are ignored.
+        server.receive(player_id, &fire(true).to_bytes());
+        run(&mut server, stats.delay * f32::from(stats.clip) + 1.0);
+        assert_eq!(server.player(player_id).unwrap().clip_ammo, 0);
+        assert!(server.shoot(player_id).is_none());
+    }
+
+    #[test]
+    fn map_change() {
+        let mut server = Server::new(Config::default(), World::new());
+        let player_id = server.connect(Ipv4Addr::LOCALHOST, 3).unwrap();
+        server.take_output();
+
+        // A map change interrupting the transfer of the previous map.
+        let start = MapStart75 { size: 1 };
+        let client = server.clients.get_mut(&player_id).unwrap();
+        client.connection.change_map();
+        client
+            .connection
+            .server_message(&Msg::MapStart75(start))
+            .unwrap();
+
+        server.change_map(World::new());
+        let received = packets(&mut server);
+        assert!(matches!(received[0].1, Msg::MapStart75(_)));
+        assert!(matches!(received.last(), Some((_, Msg::StateData(_)))));
+        assert!(server.clients[&player_id].connection.is_loaded());
+    }
+
+    #[test]
+    fn chat_commands() {
+        let config = Config {
+            admin_password: Some("secret".to_owned()),
+            ..Config::default()
+        };
+        let mut server = Server::new(config, World::new());
+
+        let ids = [
+            server.connect(Ipv4Addr::LOCALHOST, 3).unwrap(),
+            server.connect(Ipv4Addr::LOCALHOST, 3).unwrap(),
+        ];
+        for (player_id, name) in ids.into_iter().zip(["Admin", "Griefer"]) {
+            let join = ExisitingPlayer {
+                player_id,
+                team: Team::BLUE,
+                weapon: WeaponKind::Rifle,
+                held_item: ToolKind::Gun,
+                kills: 0,
+                color: Color::new_rgb(0, 0, 0),
+                name: name.to_owned(),
+            };
+            server.receive(player_id, &join.to_bytes());
+        }
+        server.take_output();
+
+        let chat = |server: &mut Server, message: &str| {
+            let chat = ChatMessage {
+                player_id: ids[0],
+                kind: ChatKind::All,
+                message: message.to_owned(),
+            };
+            server.receive(ids[0], &chat.to_bytes());
+            server.take_output()
+        };
+
+        let output = chat(&mut server, "/kick grief");
+        assert!(matches!(
+            &output[..],
+            [Output::Packet { target: Target::Player(id), .. }] if *id == ids[0]
+        ));
+
+        chat(&mut server, "/login secret");
+        let output = chat(&mut server, "/kick grief");
+        assert_eq!(
+            output.last(),
+            Some(&Output::Disconnect {
+                player_id: ids[1],
+                reason: DisconnectReason::Kicked
+            })
+        );
+
+        chat(&mut server, "/ban grief 1h cheating");
+        assert_eq!(
+            server.connect(Ipv4Addr::LOCALHOST, 3),
+            Err(DisconnectReason::Banned)
+        );
+    }
+
+    #[test]
+    fn loadout_changes() {
+        let config = Config {
+            max_team_difference: 1,
+            ..Config::default()
+        };
+        let mut server = Server::new(config, World::new());
+
+        let ids = [
+            server.connect(Ipv4Addr::LOCALHOST, 3).unwrap(),
+            server.connect(Ipv4Addr::LOCALHOST, 3).unwrap(),
+        ];
+        for player_id in ids {
+            let join = ExisitingPlayer {
+                player_id,
+                team: Team::BLUE,
+                weapon: WeaponKind::Rifle,
+                held_item: ToolKind::Gun,
+                kills: 0,
+                color: Color::new_rgb(0, 0, 0),
+                name: "Deuce".to_owned(),
+            };
+            server.receive(player_id, &join.to_bytes());
+        }
+        server.take_output();
+        // Blue was full for the second player.
+        assert_eq!(server.player(ids[1]).unwrap().team, Team::GREEN);
+
+        let change = LoadoutChange::Team(Team::GREEN);
+        server.receive(ids[0], &change.request(ids[0]).to_bytes());
+        assert!(matches!(
+            &packets(&mut server)[..],
+            [(Target::Player(id), Msg::ChatMessage(_))] if *id == ids[0]
+        ));
+
+        let change = LoadoutChange::Weapon(WeaponKind::Shotgun);
+        let mut pending = PendingChange::new(ids[0], change);
+        server.receive(ids[0], &pending.request().to_bytes());
+        let received = packets(&mut server);
+        assert!(matches!(
+            &received[..],
+            [
+                (Target::All, Msg::KillAction(_)),
+                (Target::All, Msg::ChangeWeapon(_))
+            ]
+        ));
+        assert!(!pending.update(&received[0].1));
+        assert!(pending.is_killed());
+        assert!(pending.update(&received[1].1));
+        assert!(!server.player(ids[0]).unwrap().alive);
+
+        server.tick(f32::from(server.config.spawn.change_respawn_time));
+        assert!(packets(&mut server).iter().any(|(_, msg)| matches!(
+            msg,
+            Msg::CreatePlayer(CreatePlayer {
+                weapon: WeaponKind::Shotgun,
+                ..
+            })
+        )));
+    }
+}
@@ -0,0 +1,155 @@
+//! Where and when players spawn: each team has a zone of the map, in which a safe spot on the
+//! ground is picked, and dead players wait for a delay before respawning.
+//!
+//! After a team or weapon change, see [`sprot::loadout`], the player respawns after
+//! [`SpawnConfig::change_respawn_time`].
+
+use std::ops::Range;
+
+use rand::Rng;
+use sprot::{
+    msg::model::{BlockPosition, Position, Team},
+    weapon::RESPAWN_TIME,
+    world::{World, MAX_EDIT_Z},
+};
+
+/// Random columns tried before settling for the middle of a zone.
+const ATTEMPTS: usize = 32;
+
+/// Blocks the ground around a safe spot may be higher or lower than the spot.
+const MAX_STEP: i32 = 2;
+
+/// Height of the eyes of a standing player above the ground.
+const EYE_HEIGHT: f32 = 2.4;
+
+/// A rectangle of columns on the map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnZone {
+    pub x: Range<i32>,
+    pub y: Range<i32>,
+}
+
+impl SpawnZone {
+    pub const fn new(x: Range<i32>, y: Range<i32>) -> Self {
+        Self { x, y }
+    }
+
+    /// The eyes of a player standing on a safe spot in the zone.
+    pub fn position(&self, world: &World) -> Position {
+        let ground = self.ground(world);
+
+        Position::new_xyz(ground.x, ground.y, ground.z - EYE_HEIGHT)
+    }
+
+    /// A safe spot on the ground in the zone, e.g. for an objective.
+    pub fn ground(&self, world: &World) -> Position {
+        let mut rng = rand::thread_rng();
+
+        let column = (0..ATTEMPTS)
+            .filter(|_| !self.x.is_empty() && !self.y.is_empty())
+            .map(|_| (rng.gen_range(self.x.clone()), rng.gen_range(self.y.clone())))
+            .find_map(|(x, y)| Some((x, y, safe_ground(world, x, y)?)));
+        let (x, y, z) = column.unwrap_or_else(|| {
+            let (x, y) = (
+                (self.x.start + self.x.end) / 2,
+                (self.y.start + self.y.end) / 2,
+            );
+            (x, y, world.ground_level(x, y))
+        });
+
+        Position::new_xyz(x as f32 + 0.5, y as f32 + 0.5, z as f32)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnConfig {
+    /// The zones of blue and green.
+    pub zones: [SpawnZone; 2],
+    /// Where spectators are placed.
+    pub spectators: SpawnZone,
+    /// Seconds a killed player waits before respawning.
+    pub respawn_time: u8,
+    /// Seconds a player waits after changing the team or weapon.
+    pub change_respawn_time: u8,
+}
+
+impl SpawnConfig {
+    pub fn zone(&self, team: Team) -> &SpawnZone {
+        team.index()
+            .map_or(&self.spectators, |index| &self.zones[index])
+    }
+
+    /// Where a player of `team` spawns.
+    pub fn position(&self, world: &World, team: Team) -> Position {
+        self.zone(team).position(world)
+    }
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            zones: [
+                SpawnZone::new(0..128, 128..384),
+                SpawnZone::new(384..512, 128..384),
+            ],
+            spectators: SpawnZone::new(128..384, 128..384),
+            respawn_time: RESPAWN_TIME,
+            change_respawn_time: RESPAWN_TIME,
+        }
+    }
+}
+
+/// The height of the ground of a column if a player can stand on it: above the water and
+/// with the ground around it at about the same height, so the spot is not a pillar or a pit.
+fn safe_ground(world: &World, x: i32, y: i32) -> Option<i32> {
+    let z = world.ground_level(x, y);
+    if z >= MAX_EDIT_Z || !World::in_bounds(BlockPosition::new_xyz(x, y, z)) {
+        return None;
+    }
+
+    let flat = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+        .into_iter()
+        .all(|(dx, dy)| (world.ground_level(x + dx, y + dy) - z).abs() <= MAX_STEP);
+
+    flat.then_some(z)
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::msg::model::Color;
+
+    use super::*;
+
+    #[test]
+    fn safe_spots() {
+        let mut world = World::new();
+        for x in 0..8 {
+            for y in 0..8 {
+                world.set(
+                    BlockPosition::new_xyz(x, y, 40),
+                    Some(Color::new_rgb(0, 0, 0)),
+                );
+            }
+        }
+        // A pillar in the middle of the platform.
+        for z in 30..40 {
+            world.set(
+                BlockPosition::new_xyz(4, 4, z),
+                Some(Color::new_rgb(0, 0, 0)),
+            );
+        }
+
+        assert_eq!(safe_ground(&world, 2, 2), Some(40));
+        assert_eq!(safe_ground(&world, 4, 4), None);
+        assert_eq!(safe_ground(&world, 4, 5), None);
+        // The water around the platform.
+        assert_eq!(safe_ground(&world, 20, 20), None);
+
+        let zone = SpawnZone::new(0..8, 0..8);
+        for _ in 0..20 {
+            let position = zone.position(&world);
+            assert!(position.x < 8.0 && position.y < 8.0);
+            assert!((position.z - (40.0 - EYE_HEIGHT)).abs() < 1e-4);
+        }
+    }
+}
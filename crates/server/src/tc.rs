@@ -0,0 +1,291 @@
+//! Territory control rules: players capture the command posts they stand near, outnumbering
+//! the other team speeds up the capture. The team that owns every post wins.
+//!
+//! Like the CTF rules, the rules produce the objective messages, which are applied to the
+//! [`TCModeState`] and have to be sent by the server.
+
+use rand::Rng;
+use sprot::{
+    gamemode::{TCModeState, Territory},
+    msg::{
+        model::{CaptureKind, GameMode, PlayerId, Position, Team},
+        msg::{MoveObject, Msg, ProgressBar, StateDataAddition, TerritoryCapture},
+    },
+    state::Player,
+    world::World,
+};
+
+use crate::gamemode::{Context, GameModeRules};
+
+/// Command posts of a match.
+pub const DEFAULT_TERRITORY_COUNT: usize = 7;
+
+/// Blocks between the eyes of a player and a command post the player captures.
+pub const CAPTURE_DISTANCE: f32 = 16.0;
+
+/// Progress per second and rate unit, see [`sprot::gamemode::CaptureProgress`].
+pub const CAPTURE_RATE: f32 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TCRules {
+    state: TCModeState,
+    /// The territories at the start of the match, restored after a win.
+    initial: Vec<Territory>,
+    /// Position of each territory between the teams, `0` is owned by blue and `1` by green.
+    balances: Vec<f32>,
+    /// The capturing team and rate last sent for each territory.
+    sent: Vec<Option<(Team, i8)>>,
+}
+
+impl TCRules {
+    pub fn new(territories: Vec<Territory>) -> Self {
+        Self {
+            balances: territories.iter().map(|t| balance(t.owner)).collect(),
+            sent: vec![None; territories.len()],
+            state: TCModeState::new(territories.clone()),
+            initial: territories,
+        }
+    }
+
+    /// A match with `count` command posts spread from the blue to the green side of the map.
+    /// Blue and green own the posts on their third of the map, the others are neutral. The
+    /// count is limited to the 255 posts a `TCState` holds.
+    pub fn random(world: &World, count: usize) -> Self {
+        let count = count.clamp(1, usize::from(u8::MAX));
+        let territories = (0..count)
+            .map(|index| {
+                let x = (index * 512 + 256) / count;
+                let owner = match x {
+                    0..=170 => Team::BLUE,
+                    341.. => Team::GREEN,
+                    _ => Team::NEUTRAL,
+                };
+                let y = rand::thread_rng().gen_range(128..384);
+                let z = world.ground_level(x as i32, y);
+
+                Territory {
+                    position: Position::new_xyz(x as f32 + 0.5, y as f32 + 0.5, z as f32),
+                    owner,
+                    progress: None,
+                }
+            })
+            .collect();
+
+        Self::new(territories)
+    }
+
+    /// Advances the captures by the players near each post.
+    fn update<'a, I>(&mut self, dt: f32, players: I) -> Vec<Msg>
+    where
+        I: IntoIterator<Item = &'a Player>,
+    {
+        let players: Vec<&Player> = players
+            .into_iter()
+            .filter(|player| player.alive && player.team.index().is_some())
+            .collect();
+
+        let mut msgs = Vec::new();
+        for index in 0..self.balances.len() {
+            let Some(&territory) = self.state.territories().get(index) else {
+                continue;
+            };
+            let entity_id = index as u8;
+
+            let near: Vec<&Player> = players
+                .iter()
+                .copied()
+                .filter(|player| {
+                    player.position.position.distance(territory.position) <= CAPTURE_DISTANCE
+                })
+                .collect();
+            let count = |team| near.iter().filter(|player| player.team == team).count() as i32;
+            let rate = (count(Team::GREEN) - count(Team::BLUE)).clamp(-127, 127) as i8;
+
+            let balance = &mut self.balances[index];
+            *balance = (*balance + f32::from(rate) * CAPTURE_RATE * dt).clamp(0.0, 1.0);
+            let balance = *balance;
+
+            let captured = match territory.owner {
+                Team::GREEN if balance <= 0.0 => Some(Team::BLUE),
+                Team::BLUE if balance >= 1.0 => Some(Team::GREEN),
+                Team::NEUTRAL if balance <= 0.0 => Some(Team::BLUE),
+                Team::NEUTRAL if balance >= 1.0 => Some(Team::GREEN),
+                _ => None,
+            };
+            let capturer = captured.and_then(|team| near.iter().find(|p| p.team == team));
+
+            if let (Some(team), Some(capturer)) = (captured, capturer) {
+                let owned = self.state.owned_by(team) + 1;
+                let kind = if owned == self.balances.len() {
+                    CaptureKind::Winning
+                } else {
+                    CaptureKind::Losing
+                };
+
+                let capture = Msg::TerritoryCapture(TerritoryCapture {
+                    player_id: capturer.id,
+                    entity_id,
+                    kind,
+                    team,
+                });
+                self.state.update(&capture);
+                self.sent[index] = None;
+                msgs.push(capture);
+
+                if kind == CaptureKind::Winning {
+                    msgs.extend(self.reset());
+                    return msgs;
+                }
+            }
+
+            let owner = self.state.owner(entity_id).unwrap_or(Team::NEUTRAL);
+            let capturing_team = match owner {
+                Team::BLUE => Team::GREEN,
+                Team::GREEN => Team::BLUE,
+                _ if balance >= 0.5 => Team::GREEN,
+                _ => Team::BLUE,
+            };
+            // Progress and rate are towards the capturing team.
+            let (progress, rate) = if capturing_team == Team::GREEN {
+                (balance, rate)
+            } else {
+                (1.0 - balance, rate.saturating_neg())
+            };
+
+            if self.sent[index] != Some((capturing_team, rate)) {
+                let progress_bar = Msg::ProgressBar(ProgressBar {
+                    entity_id,
+                    capturing_team,
+                    rate,
+                    progress,
+                });
+                self.state.update(&progress_bar);
+                self.sent[index] = Some((capturing_team, rate));
+                msgs.push(progress_bar);
+            }
+        }
+
+        msgs
+    }
+
+    /// Restores the territories of the start of the match.
+    fn reset(&mut self) -> Vec<Msg> {
+        *self = Self::new(self.initial.clone());
+
+        self.initial
+            .iter()
+            .enumerate()
+            .map(|(index, territory)| {
+                Msg::MoveObject(MoveObject {
+                    player_id: PlayerId(index as u8),
+                    team: territory.owner,
+                    position: territory.position,
+                })
+            })
+            .collect()
+    }
+}
+
+impl GameModeRules for TCRules {
+    fn gamemode(&self) -> GameMode {
+        GameMode::TC
+    }
+
+    fn state_addition(&self) -> StateDataAddition {
+        StateDataAddition::TCState(self.state.to_state())
+    }
+
+    fn reset(&mut self, world: &World) {
+        *self = Self::random(world, self.initial.len());
+    }
+
+    fn tick(&mut self, ctx: &Context, dt: f32) -> Vec<Msg> {
+        self.update(dt, ctx.players.iter().copied())
+    }
+}
+
+/// The initial balance of a territory owned by `owner`.
+fn balance(owner: Team) -> f32 {
+    match owner {
+        Team::BLUE => 0.0,
+        Team::GREEN => 1.0,
+        _ => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::msg::model::WeaponKind;
+
+    use super::*;
+
+    #[test]
+    fn capture_and_win() {
+        let posts = [
+            Position::new_xyz(100.5, 256.5, 60.0),
+            Position::new_xyz(400.5, 256.5, 60.0),
+        ];
+        let mut rules = TCRules::new(
+            [(posts[0], Team::BLUE), (posts[1], Team::GREEN)]
+                .map(|(position, owner)| Territory {
+                    position,
+                    owner,
+                    progress: None,
+                })
+                .to_vec(),
+        );
+
+        let mut player = Player::new(PlayerId(2), "Deuce".to_owned(), Team::BLUE, WeaponKind::Smg);
+        player.spawn(posts[1]);
+
+        let msgs = rules.update(1.0, [&player]);
+        assert_eq!(
+            msgs[0],
+            Msg::ProgressBar(ProgressBar {
+                entity_id: 0,
+                capturing_team: Team::GREEN,
+                rate: 0,
+                progress: 0.0,
+            })
+        );
+        let Msg::ProgressBar(progress_bar) = &msgs[1] else {
+            panic!("expected a progress bar, got {:?}", msgs[1]);
+        };
+        assert_eq!(
+            (
+                progress_bar.entity_id,
+                progress_bar.capturing_team,
+                progress_bar.rate
+            ),
+            (1, Team::BLUE, 1)
+        );
+        assert!((progress_bar.progress - 0.05).abs() < 1e-4);
+        assert!(rules.update(1.0, [&player]).is_empty());
+
+        // A second player captures twice as fast, spectators do not help.
+        let mut others = [3, 4].map(|id| {
+            let mut other = player.clone();
+            other.id = PlayerId(id);
+            other
+        });
+        others[1].team = Team::SPECTATOR;
+        let msgs = rules.update(1.0, [&player, &others[0], &others[1]]);
+        assert!(matches!(
+            msgs[..],
+            [Msg::ProgressBar(ProgressBar { rate: 2, .. })]
+        ));
+
+        let msgs = rules.update(10.0, [&player, &others[0]]);
+        assert_eq!(
+            msgs[0],
+            Msg::TerritoryCapture(TerritoryCapture {
+                player_id: PlayerId(2),
+                entity_id: 1,
+                kind: CaptureKind::Winning,
+                team: Team::BLUE,
+            })
+        );
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(rules.state.owner(1), Some(Team::GREEN));
+    }
+}
@@ -0,0 +1,129 @@
+//! Team deathmatch: killing a player of the other team scores a point, the first team to reach
+//! the kill limit wins.
+//!
+//! Clients only know CTF and TC, so the match is reported as CTF with the intels and bases out
+//! of reach, and every kill is sent as an intel capture to update the scores.
+
+use sprot::{
+    gamemode::CTFModeState,
+    msg::{
+        model::{CaptureKind, GameMode, IntelLocation, Position},
+        msg::{IntelCapture, KillAction, Msg, StateDataAddition},
+    },
+    world::World,
+};
+
+use crate::gamemode::{Context, GameModeRules};
+
+pub const DEFAULT_KILL_LIMIT: u8 = 50;
+
+/// The corner at the top of the map, where no player can get to.
+const HIDDEN: Position = Position::new_xyz(0.0, 0.0, 0.0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamDeathmatch {
+    state: CTFModeState,
+}
+
+impl TeamDeathmatch {
+    pub const fn new(kill_limit: u8) -> Self {
+        Self {
+            state: CTFModeState::new(kill_limit, [IntelLocation::Dropped(HIDDEN); 2], [HIDDEN; 2]),
+        }
+    }
+}
+
+impl GameModeRules for TeamDeathmatch {
+    fn gamemode(&self) -> GameMode {
+        GameMode::CTF
+    }
+
+    fn state_addition(&self) -> StateDataAddition {
+        StateDataAddition::CTFState(self.state.to_state())
+    }
+
+    fn reset(&mut self, _world: &World) {
+        *self = Self::new(self.state.capture_limit());
+    }
+
+    fn kill(&mut self, ctx: &Context, kill: &KillAction) -> Vec<Msg> {
+        let (Some(killer), Some(victim)) = (ctx.player(kill.killer_id), ctx.player(kill.player_id))
+        else {
+            return Vec::new();
+        };
+        let Some(score) = self.state.score(killer.team) else {
+            return Vec::new();
+        };
+        if killer.team == victim.team {
+            return Vec::new();
+        }
+
+        let kind = if score.saturating_add(1) >= self.state.capture_limit() {
+            CaptureKind::Winning
+        } else {
+            CaptureKind::Losing
+        };
+        let capture = Msg::IntelCapture(IntelCapture {
+            player_id: killer.id,
+            kind,
+        });
+        self.state.update(&capture, |_| Some(killer.team));
+
+        if kind == CaptureKind::Winning {
+            *self = Self::new(self.state.capture_limit());
+        }
+
+        vec![capture]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sprot::{
+        msg::model::{KillKind, PlayerId, Team, WeaponKind},
+        state::Player,
+    };
+
+    use super::*;
+
+    #[test]
+    fn kills_score() {
+        let mut tdm = TeamDeathmatch::new(2);
+        let world = World::new();
+        let players = [(0, Team::BLUE), (1, Team::GREEN), (2, Team::GREEN)]
+            .map(|(id, team)| Player::new(PlayerId(id), format!("{id}"), team, WeaponKind::Rifle));
+        let players: Vec<&Player> = players.iter().collect();
+        let ctx = Context {
+            world: &world,
+            players: &players,
+        };
+        let kill = |killer_id, player_id| KillAction {
+            player_id: PlayerId(player_id),
+            killer_id: PlayerId(killer_id),
+            kind: KillKind::Headshot,
+            respawn_time: 5,
+        };
+
+        // Team kills and suicides do not count.
+        assert!(tdm.kill(&ctx, &kill(1, 2)).is_empty());
+        assert!(tdm.kill(&ctx, &kill(0, 0)).is_empty());
+
+        assert_eq!(
+            tdm.kill(&ctx, &kill(0, 1)),
+            [Msg::IntelCapture(IntelCapture {
+                player_id: PlayerId(0),
+                kind: CaptureKind::Losing
+            })]
+        );
+        assert_eq!(tdm.state.score(Team::BLUE), Some(1));
+
+        assert_eq!(
+            tdm.kill(&ctx, &kill(0, 2)),
+            [Msg::IntelCapture(IntelCapture {
+                player_id: PlayerId(0),
+                kind: CaptureKind::Winning
+            })]
+        );
+        assert_eq!(tdm.state.score(Team::BLUE), Some(0));
+    }
+}
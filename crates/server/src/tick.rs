@@ -0,0 +1,184 @@
+//! Fixed-rate scheduling of the game loop.
+//!
+//! The game is advanced in ticks of a fixed length, independent of how often the loop runs, so
+//! physics and grenades behave the same on every server. World updates are sent every few
+//! ticks.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use sprot::physics::TICK_RATE;
+
+/// Ticks behind schedule after which the scheduler gives up catching up.
+pub const MAX_LAG_TICKS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickRates {
+    /// Ticks per second.
+    pub tick_rate: u32,
+    /// `WorldUpdate`s per second, at most `tick_rate`.
+    pub update_rate: u32,
+}
+
+impl Default for TickRates {
+    fn default() -> Self {
+        Self {
+            tick_rate: TICK_RATE,
+            update_rate: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    pub number: u64,
+    /// Whether a `WorldUpdate` has to be sent after this tick.
+    pub world_update: bool,
+}
+
+/// Measurements of the time spent in ticks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TickTiming {
+    pub ticks: u64,
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+    /// Ticks that took longer than the tick interval.
+    pub overruns: u64,
+    /// Ticks dropped because the loop fell too far behind.
+    pub skipped: u64,
+}
+
+impl TickTiming {
+    pub fn average(&self) -> Duration {
+        match u32::try_from(self.ticks) {
+            Ok(0) => Duration::ZERO,
+            Ok(ticks) => self.total / ticks,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.ticks as f64),
+        }
+    }
+}
+
+impl fmt::Display for TickTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ticks, average {:?}, max {:?}, {} overruns, {} skipped",
+            self.ticks,
+            self.average(),
+            self.max,
+            self.overruns,
+            self.skipped
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    interval: Duration,
+    /// Ticks between two world updates.
+    update_interval: u64,
+    next_tick: Instant,
+    number: u64,
+    timing: TickTiming,
+}
+
+impl Scheduler {
+    pub fn new(rates: TickRates, now: Instant) -> Self {
+        let tick_rate = rates.tick_rate.max(1);
+        let update_rate = rates.update_rate.clamp(1, tick_rate);
+
+        Self {
+            interval: Duration::from_secs(1) / tick_rate,
+            update_interval: u64::from(tick_rate / update_rate),
+            next_tick: now,
+            number: 0,
+            timing: TickTiming::default(),
+        }
+    }
+
+    /// Seconds the game advances per tick.
+    pub fn dt(&self) -> f32 {
+        self.interval.as_secs_f32()
+    }
+
+    pub const fn timing(&self) -> &TickTiming {
+        &self.timing
+    }
+
+    /// Time until the next tick is due, to be used as the timeout when polling the network.
+    pub fn timeout(&self, now: Instant) -> Duration {
+        self.next_tick.saturating_duration_since(now)
+    }
+
+    /// Returns the next tick if it is due.
+    ///
+    /// Call repeatedly until `None` to catch up after a slow iteration. If the loop is more than
+    /// [`MAX_LAG_TICKS`] behind, the missed ticks are skipped.
+    pub fn poll(&mut self, now: Instant) -> Option<Tick> {
+        if now < self.next_tick {
+            return None;
+        }
+
+        let behind = now.duration_since(self.next_tick);
+        if behind > self.interval * MAX_LAG_TICKS {
+            let skipped = (behind.as_nanos() / self.interval.as_nanos()) as u64;
+            self.timing.skipped += skipped;
+            self.number += skipped;
+            self.next_tick = now;
+        }
+
+        let tick = Tick {
+            number: self.number,
+            world_update: self.number.is_multiple_of(self.update_interval),
+        };
+        self.number += 1;
+        self.next_tick += self.interval;
+
+        Some(tick)
+    }
+
+    /// Records how long the last tick took.
+    pub fn record(&mut self, duration: Duration) {
+        let timing = &mut self.timing;
+
+        timing.ticks += 1;
+        timing.last = duration;
+        timing.max = timing.max.max(duration);
+        timing.total += duration;
+        if duration > self.interval {
+            timing.overruns += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_rate() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(TickRates::default(), start);
+
+        let mut ticks = Vec::new();
+        for ms in [250, 500] {
+            let now = start + Duration::from_millis(ms);
+            ticks.extend(std::iter::from_fn(|| scheduler.poll(now)));
+        }
+        assert_eq!(ticks.len(), 31);
+        assert_eq!(ticks.iter().filter(|tick| tick.world_update).count(), 6);
+        assert!(scheduler.timeout(start + Duration::from_millis(500)) > Duration::ZERO);
+
+        // After a long stall the missed ticks are skipped.
+        let late = start + Duration::from_secs(10);
+        let ticks = std::iter::from_fn(|| scheduler.poll(late)).count();
+        assert_eq!(ticks, 1);
+        assert!(scheduler.timing().skipped > 500);
+
+        scheduler.record(Duration::from_millis(20));
+        assert_eq!(scheduler.timing().overruns, 1);
+    }
+}
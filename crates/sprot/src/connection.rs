@@ -0,0 +1,327 @@
+//! The state of a connection during the join sequence, shared by client and server.
+//!
+//! A connection goes through `Connecting -> MapTransfer -> StateData -> Joined`: the server sends
+//! `MapStart` and the `MapChunk`s, followed by the `StateData`, and the client joins with an
+//! `ExistingPlayer`. A map change starts another `MapTransfer`, see [`Connection::change_map`].
+//!
+//! Both sides feed every message into the state machine, the server calls
+//! [`Connection::server_message`] for sent and [`Connection::client_message`] for received
+//! messages, the client the other way round.
+
+use std::fmt;
+
+use crate::msg::{
+    model::{DisconnectReason, ProtocolVersion},
+    msg::Msg,
+    MessageKind,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Connected, waiting for the map.
+    Connecting,
+    MapTransfer {
+        /// Size of the compressed map, as announced by `MapStart`.
+        size: u32,
+        received: u32,
+    },
+    /// The map and the state were sent, waiting for the `ExistingPlayer` of the client.
+    StateData,
+    Joined,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// The ENet connect data does not name a supported protocol version.
+    WrongProtocolVersion(u32),
+    /// The `VersionHandshakeResponse` does not match the challenge.
+    HandshakeFailed {
+        expected: Option<u32>,
+        received: u32,
+    },
+    UnexpectedMessage {
+        phase: Phase,
+        kind: MessageKind,
+    },
+    /// More map data than announced by `MapStart`.
+    MapSizeExceeded {
+        size: u32,
+        received: u32,
+    },
+}
+
+impl ConnectionError {
+    /// The reason sent to the peer when the connection is closed because of this error.
+    pub const fn disconnect_reason(&self) -> DisconnectReason {
+        match self {
+            Self::WrongProtocolVersion(_) | Self::HandshakeFailed { .. } => {
+                DisconnectReason::WrongProtocolVersion
+            }
+            Self::UnexpectedMessage { .. } | Self::MapSizeExceeded { .. } => {
+                DisconnectReason::Kicked
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongProtocolVersion(version) => {
+                write!(f, "Unsupported protocol version {version}")
+            }
+            Self::HandshakeFailed { expected, received } => write!(
+                f,
+                "Handshake failed, expected challenge {expected:?} but received {received}"
+            ),
+            Self::UnexpectedMessage { phase, kind } => {
+                write!(f, "Unexpected message {kind:?} in phase {phase:?}")
+            }
+            Self::MapSizeExceeded { size, received } => {
+                write!(f, "Received {received} bytes of a map with {size} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    version: ProtocolVersion,
+    phase: Phase,
+    /// Challenge of a `VersionHandshakeInit` that was not answered yet.
+    challenge: Option<u32>,
+    /// Whether the client joined before, messages of the old map may still arrive after a map
+    /// change.
+    was_joined: bool,
+}
+
+impl Connection {
+    pub const fn new(version: ProtocolVersion) -> Self {
+        Self {
+            version,
+            phase: Phase::Connecting,
+            challenge: None,
+            was_joined: false,
+        }
+    }
+
+    /// Accepts a connection with the data the client sent with the ENet connect.
+    pub fn accept(data: u32) -> Result<Self, ConnectionError> {
+        u8::try_from(data)
+            .ok()
+            .and_then(|version| ProtocolVersion::try_from(version).ok())
+            .map(Self::new)
+            .ok_or(ConnectionError::WrongProtocolVersion(data))
+    }
+
+    /// The data the client has to send with the ENet connect.
+    pub const fn connect_data(&self) -> u32 {
+        self.version.to_number() as u32
+    }
+
+    pub const fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    pub const fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub const fn is_joined(&self) -> bool {
+        matches!(self.phase, Phase::Joined)
+    }
+
+    /// Whether the client has the map and the state and has to receive game updates.
+    pub const fn is_loaded(&self) -> bool {
+        matches!(self.phase, Phase::StateData | Phase::Joined)
+    }
+
+    /// Progress of the map transfer between 0 and 1.
+    pub fn map_progress(&self) -> Option<f32> {
+        match self.phase {
+            Phase::MapTransfer { size, received } if size > 0 => {
+                Some((received as f32 / size as f32).min(1.0))
+            }
+            Phase::MapTransfer { .. } => Some(0.0),
+            _ => None,
+        }
+    }
+
+    /// Starts over with the map transfer for a map change, even if the transfer of the previous
+    /// map was not finished.
+    pub const fn change_map(&mut self) {
+        self.phase = Phase::Connecting;
+    }
+
+    /// Advances the state with a message sent by the server.
+    pub const fn server_message(&mut self, msg: &Msg) -> Result<(), ConnectionError> {
+        self.phase = match (self.phase, msg) {
+            (_, Msg::VersionHandshakeInit(init)) => {
+                self.challenge = Some(init.challenge);
+                self.phase
+            }
+            (_, Msg::VersionGet(_)) => self.phase,
+            (Phase::MapTransfer { .. }, Msg::MapStart75(_) | Msg::MapStart76(_)) => {
+                return Err(self.unexpected(msg));
+            }
+            (_, Msg::MapStart75(start)) => Phase::MapTransfer {
+                size: start.size,
+                received: 0,
+            },
+            (_, Msg::MapStart76(start)) => Phase::MapTransfer {
+                size: start.size,
+                received: 0,
+            },
+            (Phase::MapTransfer { size, received }, Msg::MapChunk(chunk)) => {
+                let received = received.saturating_add(chunk.data.len() as u32);
+                if received > size {
+                    return Err(ConnectionError::MapSizeExceeded { size, received });
+                }
+
+                Phase::MapTransfer { size, received }
+            }
+            (Phase::MapTransfer { size, received }, Msg::StateData(_)) if received == size => {
+                Phase::StateData
+            }
+            (Phase::StateData | Phase::Joined, msg)
+                if !matches!(msg, Msg::MapChunk(_) | Msg::StateData(_)) =>
+            {
+                self.phase
+            }
+            _ => return Err(self.unexpected(msg)),
+        };
+
+        Ok(())
+    }
+
+    /// Advances the state with a message sent by the client.
+    ///
+    /// Returns `false` if the message should be dropped, because it was sent for the previous
+    /// map.
+    pub const fn client_message(&mut self, msg: &Msg) -> Result<bool, ConnectionError> {
+        match (self.phase, msg) {
+            (_, Msg::VersionHandshakeResponse(response)) => match self.challenge.take() {
+                Some(challenge) if challenge == response.challenge => Ok(true),
+                expected => Err(ConnectionError::HandshakeFailed {
+                    expected,
+                    received: response.challenge,
+                }),
+            },
+            (_, Msg::VersionResponse(_)) | (Phase::MapTransfer { .. }, Msg::MapCached(_)) => {
+                Ok(true)
+            }
+            (Phase::StateData, Msg::ExisitingPlayer(_)) => {
+                self.phase = Phase::Joined;
+                self.was_joined = true;
+
+                Ok(true)
+            }
+            (Phase::Joined, msg) if !matches!(msg, Msg::ExisitingPlayer(_) | Msg::MapCached(_)) => {
+                Ok(true)
+            }
+            (Phase::MapTransfer { .. } | Phase::StateData, _) if self.was_joined => Ok(false),
+            _ => Err(self.unexpected(msg)),
+        }
+    }
+
+    const fn unexpected(&self, msg: &Msg) -> ConnectionError {
+        ConnectionError::UnexpectedMessage {
+            phase: self.phase,
+            kind: msg.kind(),
+        }
+    }
+}
+
+/// The reason the peer gave for closing the connection, from the ENet disconnect data.
+pub fn disconnect_reason(data: u32) -> Option<DisconnectReason> {
+    u8::try_from(data)
+        .ok()
+        .and_then(|reason| DisconnectReason::try_from(reason).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{
+        model::{Color, GameMode, PlayerId, Position, Team, ToolKind, WeaponKind},
+        msg::{
+            ExisitingPlayer, MapChunk, MapStart75, PositionData, StateData, VersionHandshakeInit,
+            VersionHandshakeResponse,
+        },
+    };
+
+    #[test]
+    fn join_sequence() {
+        assert_eq!(
+            Connection::accept(5),
+            Err(ConnectionError::WrongProtocolVersion(5))
+        );
+
+        let mut connection = Connection::accept(3).unwrap();
+        assert_eq!(connection.version(), ProtocolVersion::V_0_75);
+
+        let position = Msg::PositionData(PositionData {
+            position: Position::default(),
+        });
+        let join = Msg::ExisitingPlayer(ExisitingPlayer {
+            player_id: PlayerId(0),
+            team: Team::BLUE,
+            weapon: WeaponKind::Rifle,
+            held_item: ToolKind::Gun,
+            kills: 0,
+            color: Color::new_rgb(0, 0, 0),
+            name: "Deuce".to_owned(),
+        });
+        assert_eq!(
+            connection
+                .client_message(&position)
+                .unwrap_err()
+                .disconnect_reason(),
+            DisconnectReason::Kicked
+        );
+
+        let init = Msg::VersionHandshakeInit(VersionHandshakeInit { challenge: 42 });
+        connection.server_message(&init).unwrap();
+        let response = Msg::VersionHandshakeResponse(VersionHandshakeResponse { challenge: 42 });
+        assert_eq!(connection.client_message(&response), Ok(true));
+        assert!(connection.client_message(&response).is_err());
+
+        let state = Msg::StateData(StateData {
+            player_id: PlayerId(0),
+            fog_color: Color::new_rgb(0, 0, 0),
+            team1_color: Color::new_rgb(0, 0, 255),
+            team2_color: Color::new_rgb(0, 255, 0),
+            team1_name: "Blue".to_owned(),
+            team2_name: "Green".to_owned(),
+            gamemode: GameMode::CTF,
+            addition: None,
+        });
+        let start = Msg::MapStart75(MapStart75 { size: 4 });
+        connection.server_message(&start).unwrap();
+        let chunk = Msg::MapChunk(MapChunk { data: vec![0; 2] });
+        connection.server_message(&chunk).unwrap();
+        assert_eq!(connection.map_progress(), Some(0.5));
+        assert!(connection.server_message(&state).is_err());
+        connection.server_message(&chunk).unwrap();
+        assert!(connection.server_message(&chunk).is_err());
+
+        connection.server_message(&state).unwrap();
+        assert!(connection.is_loaded());
+        assert_eq!(connection.client_message(&join), Ok(true));
+        assert!(connection.is_joined());
+        assert_eq!(connection.client_message(&position), Ok(true));
+
+        // Map change, messages for the old map are dropped.
+        connection.server_message(&start).unwrap();
+        assert_eq!(connection.client_message(&position), Ok(false));
+
+        // Another map change during the transfer.
+        connection.server_message(&chunk).unwrap();
+        assert!(connection.server_message(&start).is_err());
+        connection.change_map();
+        connection.server_message(&start).unwrap();
+        assert_eq!(connection.map_progress(), Some(0.0));
+    }
+}
@@ -29,3 +29,25 @@ where
 }
 
 impl<T> std::error::Error for VariantError<T> where Self: fmt::Debug + fmt::Display {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The data ended in the middle of a column.
+    UnexpectedEnd { offset: usize },
+    /// A span of a column lies outside of the map height.
+    InvalidSpan { x: i32, y: i32 },
+    /// There is data left after the last column.
+    TrailingData { offset: usize },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd { offset } => write!(f, "Map data ended unexpectedly at {offset}"),
+            Self::InvalidSpan { x, y } => write!(f, "Invalid span in column {x}, {y}"),
+            Self::TrailingData { offset } => write!(f, "Trailing map data at {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
@@ -0,0 +1,468 @@
+//! Typed objective state of the CTF and TC game modes.
+//!
+//! The initial state is taken from the [`StateDataAddition`] and is kept up to date with the
+//! objective messages the server sends during a match.
+
+use crate::msg::{
+    model::{
+        CaptureKind, GameMode, IntelFlags, IntelLocation, PlayerId, Position, Team, TerritoryData,
+    },
+    msg::{
+        CTFState, IntelCapture, IntelDrop, IntelPickup, MoveObject, Msg, ProgressBar, StateData,
+        StateDataAddition, TCState, TerritoryCapture,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameModeState {
+    CTF(CTFModeState),
+    TC(TCModeState),
+}
+
+impl GameModeState {
+    /// Returns `None` if the server did not append a state to the `StateData` message.
+    pub fn from_state_data(state: &StateData) -> Option<Self> {
+        state.addition.as_ref().map(Self::from_addition)
+    }
+
+    pub fn from_addition(addition: &StateDataAddition) -> Self {
+        match addition {
+            StateDataAddition::CTFState(ctf) => Self::CTF(CTFModeState::from_state(ctf)),
+            StateDataAddition::TCState(tc) => Self::TC(TCModeState::from_state(tc)),
+        }
+    }
+
+    pub fn to_addition(&self) -> StateDataAddition {
+        match self {
+            Self::CTF(ctf) => StateDataAddition::CTFState(ctf.to_state()),
+            Self::TC(tc) => StateDataAddition::TCState(tc.to_state()),
+        }
+    }
+
+    pub const fn gamemode(&self) -> GameMode {
+        match self {
+            Self::CTF(_) => GameMode::CTF,
+            Self::TC(_) => GameMode::TC,
+        }
+    }
+
+    /// Applies an objective message to the state.
+    ///
+    /// `team_of` is used to look up the team of a player, as `IntelPickup` does not carry it.
+    /// Returns `true` if the message was relevant for the current game mode.
+    pub fn update<F>(&mut self, msg: &Msg, team_of: F) -> bool
+    where
+        F: Fn(PlayerId) -> Option<Team>,
+    {
+        match self {
+            Self::CTF(ctf) => ctf.update(msg, team_of),
+            Self::TC(tc) => tc.update(msg),
+        }
+    }
+}
+
+/// Objective state of a capture the flag match.
+///
+/// All accessors take the team that owns the intel/base.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CTFModeState {
+    scores: [u8; 2],
+    capture_limit: u8,
+    intels: [IntelLocation; 2],
+    bases: [Position; 2],
+}
+
+impl CTFModeState {
+    pub const fn new(capture_limit: u8, intels: [IntelLocation; 2], bases: [Position; 2]) -> Self {
+        Self {
+            scores: [0, 0],
+            capture_limit,
+            intels,
+            bases,
+        }
+    }
+
+    pub const fn from_state(state: &CTFState) -> Self {
+        Self {
+            scores: [state.team1_score, state.team2_score],
+            capture_limit: state.capture_limit,
+            intels: [state.team1_intel_location, state.team2_intel_location],
+            bases: [state.team1_base, state.team2_base],
+        }
+    }
+
+    pub const fn to_state(&self) -> CTFState {
+        CTFState {
+            team1_score: self.scores[0],
+            team2_score: self.scores[1],
+            capture_limit: self.capture_limit,
+            intel_flags: IntelFlags::from_locations(&self.intels[0], &self.intels[1]),
+            team1_intel_location: self.intels[0],
+            team2_intel_location: self.intels[1],
+            team1_base: self.bases[0],
+            team2_base: self.bases[1],
+        }
+    }
+
+    pub fn score(&self, team: Team) -> Option<u8> {
+        team.index().map(|index| self.scores[index])
+    }
+
+    pub const fn capture_limit(&self) -> u8 {
+        self.capture_limit
+    }
+
+    pub fn intel(&self, team: Team) -> Option<IntelLocation> {
+        team.index().map(|index| self.intels[index])
+    }
+
+    /// The player holding the intel of `team`.
+    pub fn intel_holder(&self, team: Team) -> Option<PlayerId> {
+        self.intel(team).and_then(|intel| intel.holder())
+    }
+
+    /// The team whose intel is held by `player_id`.
+    pub fn held_intel(&self, player_id: PlayerId) -> Option<Team> {
+        [Team::BLUE, Team::GREEN]
+            .into_iter()
+            .find(|&team| self.intel_holder(team) == Some(player_id))
+    }
+
+    pub fn base(&self, team: Team) -> Option<Position> {
+        team.index().map(|index| self.bases[index])
+    }
+
+    /// The team that reached the capture limit, if any. A limit of 0 is won by the first
+    /// capture, not by both teams before the match started.
+    pub fn winner(&self) -> Option<Team> {
+        let limit = self.capture_limit.max(1);
+        [Team::BLUE, Team::GREEN]
+            .into_iter()
+            .find(|&team| self.score(team) >= Some(limit))
+    }
+
+    /// Applies a CTF message to the state, see [`GameModeState::update`].
+    pub fn update<F>(&mut self, msg: &Msg, team_of: F) -> bool
+    where
+        F: Fn(PlayerId) -> Option<Team>,
+    {
+        match msg {
+            Msg::IntelPickup(pickup) => match team_of(pickup.player_id) {
+                Some(team) => self.pickup_intel(pickup, team),
+                None => false,
+            },
+            Msg::IntelDrop(drop) => self.drop_intel(drop),
+            Msg::IntelCapture(capture) => self.capture_intel(capture, team_of(capture.player_id)),
+            Msg::MoveObject(move_object) => self.move_object(move_object),
+            _ => false,
+        }
+    }
+
+    const fn pickup_intel(&mut self, pickup: &IntelPickup, team: Team) -> bool {
+        // Players always pick up the intel of the opposing team.
+        match team.other().index() {
+            Some(index) => {
+                self.intels[index] = IntelLocation::Held(pickup.player_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn drop_intel(&mut self, drop: &IntelDrop) -> bool {
+        match self.held_intel(drop.player_id).and_then(Team::index) {
+            Some(index) => {
+                self.intels[index] = IntelLocation::Dropped(drop.position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The server sends a `MoveObject` with the new intel location after a capture, until then
+    /// the intel stays with the capturing player.
+    fn capture_intel(&mut self, capture: &IntelCapture, team: Option<Team>) -> bool {
+        let capturing_team = match self.held_intel(capture.player_id) {
+            Some(intel_team) => intel_team.other(),
+            None => match team {
+                Some(team) => team,
+                None => return false,
+            },
+        };
+
+        let Some(index) = capturing_team.index() else {
+            return false;
+        };
+
+        self.scores[index] = match capture.kind {
+            CaptureKind::Winning => self.capture_limit.max(self.scores[index].saturating_add(1)),
+            CaptureKind::Losing => self.scores[index].saturating_add(1),
+        };
+
+        true
+    }
+
+    /// Object ids `0` and `1` are the intels, `2` and `3` the bases of blue and green.
+    const fn move_object(&mut self, move_object: &MoveObject) -> bool {
+        let id = move_object.player_id.0 as usize;
+
+        match id {
+            0 | 1 => self.intels[id] = IntelLocation::Dropped(move_object.position),
+            2 | 3 => self.bases[id - 2] = move_object.position,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// Progress of a team capturing a territory, as sent by `ProgressBar`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureProgress {
+    pub capturing_team: Team,
+    /// One rate unit is 5% of progress per second.
+    pub rate: i8,
+    /// In range \[0,1\].
+    pub progress: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Territory {
+    pub position: Position,
+    pub owner: Team,
+    pub progress: Option<CaptureProgress>,
+}
+
+/// Objective state of a territory control match.
+///
+/// Territories are addressed by their entity id, which is the index in the `TCState`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TCModeState {
+    territories: Vec<Territory>,
+}
+
+impl TCModeState {
+    pub const fn new(territories: Vec<Territory>) -> Self {
+        Self { territories }
+    }
+
+    pub fn from_state(state: &TCState) -> Self {
+        let territories = state
+            .territory_data
+            .iter()
+            .map(|data| Territory {
+                position: data.position,
+                owner: data.owner_team,
+                progress: None,
+            })
+            .collect();
+
+        Self { territories }
+    }
+
+    pub fn to_state(&self) -> TCState {
+        let territory_data = self
+            .territories
+            .iter()
+            .map(|territory| TerritoryData {
+                position: territory.position,
+                owner_team: territory.owner,
+            })
+            .collect::<Vec<_>>();
+
+        TCState {
+            territory_count: territory_data.len() as u8,
+            territory_data,
+        }
+    }
+
+    pub fn territories(&self) -> &[Territory] {
+        &self.territories
+    }
+
+    pub fn territory(&self, entity_id: u8) -> Option<&Territory> {
+        self.territories.get(entity_id as usize)
+    }
+
+    pub fn owner(&self, entity_id: u8) -> Option<Team> {
+        self.territory(entity_id).map(|territory| territory.owner)
+    }
+
+    /// Number of territories owned by `team`.
+    pub fn owned_by(&self, team: Team) -> usize {
+        self.territories
+            .iter()
+            .filter(|territory| territory.owner == team)
+            .count()
+    }
+
+    /// The team that owns every territory, if any.
+    pub fn winner(&self) -> Option<Team> {
+        [Team::BLUE, Team::GREEN].into_iter().find(|&team| {
+            !self.territories.is_empty() && self.owned_by(team) == self.territories.len()
+        })
+    }
+
+    /// Applies a TC message to the state.
+    pub fn update(&mut self, msg: &Msg) -> bool {
+        match msg {
+            Msg::TerritoryCapture(capture) => self.capture(capture),
+            Msg::ProgressBar(progress) => self.progress(progress),
+            Msg::MoveObject(move_object) => self.move_object(move_object),
+            _ => false,
+        }
+    }
+
+    fn capture(&mut self, capture: &TerritoryCapture) -> bool {
+        match self.territories.get_mut(capture.entity_id as usize) {
+            Some(territory) => {
+                territory.owner = capture.team;
+                territory.progress = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn progress(&mut self, progress: &ProgressBar) -> bool {
+        match self.territories.get_mut(progress.entity_id as usize) {
+            Some(territory) => {
+                territory.progress = Some(CaptureProgress {
+                    capturing_team: progress.capturing_team,
+                    rate: progress.rate,
+                    progress: progress.progress,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn move_object(&mut self, move_object: &MoveObject) -> bool {
+        match self.territories.get_mut(move_object.player_id.0 as usize) {
+            Some(territory) => {
+                territory.position = move_object.position;
+                territory.owner = move_object.team;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctf_intel_flow() {
+        let mut state = GameModeState::CTF(CTFModeState::new(
+            3,
+            [
+                IntelLocation::Dropped(Position::new_xyz(10.0, 10.0, 30.0)),
+                IntelLocation::Dropped(Position::new_xyz(500.0, 10.0, 30.0)),
+            ],
+            [
+                Position::new_xyz(0.0, 0.0, 30.0),
+                Position::new_xyz(511.0, 0.0, 30.0),
+            ],
+        ));
+        let team_of = |_| Some(Team::BLUE);
+
+        assert!(state.update(
+            &Msg::IntelPickup(IntelPickup {
+                player_id: PlayerId(4)
+            }),
+            team_of
+        ));
+
+        let GameModeState::CTF(ctf) = &state else {
+            unreachable!()
+        };
+        assert_eq!(ctf.intel_holder(Team::GREEN), Some(PlayerId(4)));
+        assert_eq!(ctf.held_intel(PlayerId(4)), Some(Team::GREEN));
+
+        assert!(state.update(
+            &Msg::IntelCapture(IntelCapture {
+                player_id: PlayerId(4),
+                kind: CaptureKind::Losing,
+            }),
+            team_of
+        ));
+
+        let GameModeState::CTF(ctf) = &state else {
+            unreachable!()
+        };
+        assert_eq!(ctf.score(Team::BLUE), Some(1));
+        assert_eq!(ctf.score(Team::GREEN), Some(0));
+        assert_eq!(ctf.intel_holder(Team::GREEN), Some(PlayerId(4)));
+
+        // The intel returns home with the following `MoveObject`.
+        let home = Position::new_xyz(480.0, 20.0, 30.0);
+        assert!(state.update(
+            &Msg::MoveObject(MoveObject {
+                player_id: PlayerId(1),
+                team: Team::GREEN,
+                position: home,
+            }),
+            team_of
+        ));
+
+        let GameModeState::CTF(ctf) = &state else {
+            unreachable!()
+        };
+        assert_eq!(ctf.intel(Team::GREEN), Some(IntelLocation::Dropped(home)));
+        assert_eq!(ctf.winner(), None);
+
+        let unlimited = CTFModeState::new(0, ctf.intels, ctf.bases);
+        assert_eq!(unlimited.winner(), None);
+    }
+
+    #[test]
+    fn tc_capture_flow() {
+        let territory = |x, owner| Territory {
+            position: Position::new_xyz(x, 256.0, 40.0),
+            owner,
+            progress: None,
+        };
+        let mut state = TCModeState::new(vec![
+            territory(64.0, Team::BLUE),
+            territory(256.0, Team::NEUTRAL),
+        ]);
+
+        assert!(state.update(&Msg::ProgressBar(ProgressBar {
+            entity_id: 1,
+            capturing_team: Team::BLUE,
+            rate: 2,
+            progress: 0.25,
+        })));
+        assert_eq!(
+            state.territory(1).unwrap().progress,
+            Some(CaptureProgress {
+                capturing_team: Team::BLUE,
+                rate: 2,
+                progress: 0.25,
+            })
+        );
+        // Unknown territories are ignored.
+        assert!(!state.update(&Msg::ProgressBar(ProgressBar {
+            entity_id: 2,
+            capturing_team: Team::BLUE,
+            rate: 2,
+            progress: 0.25,
+        })));
+        assert_eq!(state.winner(), None);
+
+        assert!(state.update(&Msg::TerritoryCapture(TerritoryCapture {
+            player_id: PlayerId(4),
+            entity_id: 1,
+            kind: CaptureKind::Winning,
+            team: Team::BLUE,
+        })));
+        assert_eq!(state.owner(1), Some(Team::BLUE));
+        assert_eq!(state.territory(1).unwrap().progress, None);
+        assert_eq!(state.owned_by(Team::BLUE), 2);
+        assert_eq!(state.winner(), Some(Team::BLUE));
+        assert_eq!(TCModeState::from_state(&state.to_state()), state);
+    }
+}
@@ -0,0 +1,230 @@
+//! Grenade trajectories and explosions, ported from `world.c` of pysnip.
+
+use crate::{
+    msg::{
+        model::{ActionKind, BlockPosition, PlayerId, Position},
+        msg::{BlockAction, GrenadePacket},
+    },
+    physics::TICK,
+    world::{VoxelChange, World, DEFAULT_COLOR, MAP_X, MAP_Y, MAP_Z},
+};
+
+/// Fraction of the velocity a grenade keeps when bouncing off a block.
+pub const RESTITUTION: f32 = 0.36;
+
+/// Players further away than this on any axis are not hurt by an explosion.
+pub const BLAST_RANGE: f32 = 16.0;
+
+/// Bounces faster than this on any axis play a sound on the clients.
+const BOUNCE_SOUND_THRESHOLD: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bounce {
+    None,
+    Silent,
+    Loud,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grenade {
+    pub player_id: PlayerId,
+    pub position: Position,
+    pub velocity: Position,
+    /// Remaining time until the explosion in seconds.
+    pub fuse: f32,
+}
+
+impl Grenade {
+    pub const fn from_packet(packet: &GrenadePacket) -> Self {
+        Self {
+            player_id: packet.player_id,
+            position: packet.position,
+            velocity: packet.velocity,
+            fuse: packet.fuse_length,
+        }
+    }
+
+    pub const fn has_exploded(&self) -> bool {
+        self.fuse <= 0.0
+    }
+
+    /// Advances the grenade by `dt` seconds.
+    ///
+    /// Returns the explosion once the fuse expired.
+    pub fn step(&mut self, world: &World, dt: f32) -> (Bounce, Option<Explosion>) {
+        let bounce = self.move_grenade(world, dt);
+        self.fuse -= dt;
+
+        let explosion = self.has_exploded().then_some(Explosion {
+            player_id: self.player_id,
+            position: self.position,
+        });
+
+        (bounce, explosion)
+    }
+
+    /// Steps the grenade at the game tick until it explodes, returning the path and explosion.
+    pub fn simulate(mut self, world: &World) -> (Vec<Position>, Explosion) {
+        let mut path = vec![self.position];
+
+        loop {
+            let (_, explosion) = self.step(world, TICK);
+            path.push(self.position);
+
+            if let Some(explosion) = explosion {
+                return (path, explosion);
+            }
+        }
+    }
+
+    fn move_grenade(&mut self, world: &World, dt: f32) -> Bounce {
+        let previous = self.position;
+        let f = dt * 32.0;
+
+        // Friction is negligible.
+        self.velocity.z += dt;
+        self.position.x += self.velocity.x * f;
+        self.position.y += self.velocity.y * f;
+        self.position.z += self.velocity.z * f;
+
+        let block = BlockPosition::from_position(self.position);
+        if !clipworld(world, block.x, block.y, block.z) {
+            return Bounce::None;
+        }
+
+        let Position { x, y, z } = self.velocity;
+        let bounce = if x.abs() > BOUNCE_SOUND_THRESHOLD
+            || y.abs() > BOUNCE_SOUND_THRESHOLD
+            || z.abs() > BOUNCE_SOUND_THRESHOLD
+        {
+            Bounce::Loud
+        } else {
+            Bounce::Silent
+        };
+
+        // Reflect along the axis the grenade entered the block from.
+        let old = BlockPosition::from_position(previous);
+        if block.z != old.z
+            && ((block.x == old.x && block.y == old.y)
+                || !clipworld(world, block.x, block.y, old.z))
+        {
+            self.velocity.z = -self.velocity.z;
+        } else if block.x != old.x
+            && ((block.y == old.y && block.z == old.z)
+                || !clipworld(world, old.x, block.y, block.z))
+        {
+            self.velocity.x = -self.velocity.x;
+        } else if block.y != old.y
+            && ((block.x == old.x && block.z == old.z)
+                || !clipworld(world, block.x, old.y, block.z))
+        {
+            self.velocity.y = -self.velocity.y;
+        }
+
+        self.position = previous;
+        self.velocity.x *= RESTITUTION;
+        self.velocity.y *= RESTITUTION;
+        self.velocity.z *= RESTITUTION;
+
+        bounce
+    }
+}
+
+/// Whether a block collides with grenades. Everything outside of the map is air, except below.
+fn clipworld(world: &World, x: i32, y: i32, z: i32) -> bool {
+    if !(0..MAP_X).contains(&x) || !(0..MAP_Y).contains(&y) || z < 0 {
+        return false;
+    }
+
+    let z = match z {
+        63 => 62,
+        z if z >= MAP_Z => return true,
+        z => z,
+    };
+
+    world.is_solid(BlockPosition::new_xyz(x, y, z))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Explosion {
+    pub player_id: PlayerId,
+    pub position: Position,
+}
+
+impl Explosion {
+    /// Damage dealt to a player at `target`, falling off with the squared distance.
+    ///
+    /// Players out of range or without line of sight to the explosion are not hurt.
+    pub fn damage(&self, world: &World, target: Position) -> f32 {
+        let dx = target.x - self.position.x;
+        let dy = target.y - self.position.y;
+        let dz = target.z - self.position.z;
+
+        if dx.abs() >= BLAST_RANGE || dy.abs() >= BLAST_RANGE || dz.abs() >= BLAST_RANGE {
+            return 0.0;
+        }
+
+        if !world.can_see(self.position, target) {
+            return 0.0;
+        }
+
+        let distance = dx * dx + dy * dy + dz * dz;
+        if distance == 0.0 {
+            return 100.0;
+        }
+
+        4096.0 / distance
+    }
+
+    /// The `BlockAction` the server sends for the explosion.
+    pub const fn block_action(&self) -> BlockAction {
+        BlockAction {
+            player_id: self.player_id,
+            kind: ActionKind::GDestroy,
+            position: BlockPosition::from_position(self.position),
+        }
+    }
+
+    /// Destroys the blocks around the explosion, including the blocks that fall down afterwards.
+    pub fn destroy(&self, world: &mut World) -> Vec<VoxelChange> {
+        let mut changes = world.apply_block_action(&self.block_action(), DEFAULT_COLOR);
+        let collapse = world.collapse(&changes);
+
+        changes.extend(collapse.removals);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounces_and_explodes() {
+        let mut world = World::new();
+        for x in 0..32 {
+            for y in 0..32 {
+                world.set(BlockPosition::new_xyz(x, y, 40), Some(DEFAULT_COLOR));
+            }
+        }
+
+        let grenade = Grenade {
+            player_id: PlayerId(1),
+            position: Position::new_xyz(16.5, 16.5, 30.0),
+            velocity: Position::new_xyz(0.0, 0.0, 0.5),
+            fuse: 3.0,
+        };
+        let (path, explosion) = grenade.simulate(&world);
+
+        assert!((180..=182).contains(&path.len()));
+        assert!(path.iter().all(|p| p.z < 40.0));
+        assert!(explosion.position.z > 39.0);
+
+        let near = Position::new_xyz(16.5, 18.5, 38.0);
+        assert!(explosion.damage(&world, near) > 100.0);
+        assert_eq!(
+            explosion.damage(&world, Position::new_xyz(16.5, 16.5, 45.0)),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Hitscan raycasting against the map and player hitboxes, used to check `HitPacket`s.
+//!
+//! The hitboxes approximate the player model of OpenSpades. They are oriented along the yaw of
+//! the player and placed relative to the eye position.
+
+use crate::{
+    msg::model::{HitKind, PlayerId, Position},
+    world::{RayHit, World},
+};
+
+/// Shots do not reach further than the fog.
+pub const MAX_RANGE: f32 = 128.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hitbox {
+    pub kind: HitKind,
+    /// Center relative to the eyes, `x` to the right, `y` forwards and `z` downwards.
+    pub center: Position,
+    pub half_size: Position,
+}
+
+const fn hitbox(kind: HitKind, center: [f32; 3], half_size: [f32; 3]) -> Hitbox {
+    Hitbox {
+        kind,
+        center: Position::new_xyz(center[0], center[1], center[2]),
+        half_size: Position::new_xyz(half_size[0], half_size[1], half_size[2]),
+    }
+}
+
+/// Hitboxes of a standing player, whose feet are 2.25 blocks below the eyes.
+pub const STANDING_HITBOXES: [Hitbox; 4] = [
+    hitbox(HitKind::Head, [0.0, 0.0, 0.0], [0.35, 0.35, 0.35]),
+    hitbox(HitKind::Torso, [0.0, 0.0, 0.8], [0.45, 0.3, 0.45]),
+    hitbox(HitKind::Arms, [0.0, 0.45, 0.75], [0.45, 0.25, 0.15]),
+    hitbox(HitKind::Legs, [0.0, 0.0, 1.75], [0.4, 0.3, 0.5]),
+];
+
+/// Hitboxes of a crouching player, whose feet are 1.35 blocks below the eyes.
+pub const CROUCHING_HITBOXES: [Hitbox; 4] = [
+    hitbox(HitKind::Head, [0.0, 0.0, 0.0], [0.35, 0.35, 0.35]),
+    hitbox(HitKind::Torso, [0.0, 0.0, 0.65], [0.45, 0.3, 0.3]),
+    hitbox(HitKind::Arms, [0.0, 0.45, 0.6], [0.45, 0.25, 0.15]),
+    hitbox(HitKind::Legs, [0.0, 0.1, 1.15], [0.4, 0.4, 0.2]),
+];
+
+/// A player that can be hit by a shot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub player_id: PlayerId,
+    pub position: Position,
+    pub orientation: Position,
+    pub crouching: bool,
+}
+
+impl Target {
+    pub const fn hitboxes(&self) -> &'static [Hitbox; 4] {
+        if self.crouching {
+            &CROUCHING_HITBOXES
+        } else {
+            &STANDING_HITBOXES
+        }
+    }
+
+    /// Center of a hitbox in world coordinates.
+    pub fn hitbox_center(&self, kind: HitKind) -> Option<Position> {
+        let hitbox = self.hitboxes().iter().find(|hitbox| hitbox.kind == kind)?;
+        let (right, forward) = self.axes();
+        let c = hitbox.center;
+
+        Some(Position::new_xyz(
+            self.position.x + right.0 * c.x + forward.0 * c.y,
+            self.position.y + right.1 * c.x + forward.1 * c.y,
+            self.position.z + c.z,
+        ))
+    }
+
+    /// Intersects a ray with the hitboxes, returning the closest hit body part and its distance.
+    pub fn intersect(&self, origin: Position, direction: Position) -> Option<(HitKind, f32)> {
+        let (right, forward) = self.axes();
+
+        // Transform the ray into the frame of the player.
+        let local = |x: f32, y: f32| (x * right.0 + y * right.1, x * forward.0 + y * forward.1);
+        let (ox, oy) = local(origin.x - self.position.x, origin.y - self.position.y);
+        let (dx, dy) = local(direction.x, direction.y);
+        let origin = [ox, oy, origin.z - self.position.z];
+        let direction = [dx, dy, direction.z];
+
+        self.hitboxes()
+            .iter()
+            .filter_map(|hitbox| {
+                let center = [hitbox.center.x, hitbox.center.y, hitbox.center.z];
+                let half = [hitbox.half_size.x, hitbox.half_size.y, hitbox.half_size.z];
+
+                slab_intersection(origin, direction, center, half).map(|t| (hitbox.kind, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Unit vectors of the right and forward axes of the player in the horizontal plane.
+    fn axes(&self) -> ((f32, f32), (f32, f32)) {
+        let length = (self.orientation.x * self.orientation.x
+            + self.orientation.y * self.orientation.y)
+            .sqrt();
+        let forward = if length > 0.0 {
+            (self.orientation.x / length, self.orientation.y / length)
+        } else {
+            (1.0, 0.0)
+        };
+
+        ((-forward.1, forward.0), forward)
+    }
+}
+
+/// Distance along the ray to the box, if the ray hits it in front of the origin.
+fn slab_intersection(
+    origin: [f32; 3],
+    direction: [f32; 3],
+    center: [f32; 3],
+    half: [f32; 3],
+) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = f32::INFINITY;
+
+    for axis in 0..3 {
+        let min = center[axis] - half[axis] - origin[axis];
+        let max = center[axis] + half[axis] - origin[axis];
+
+        if direction[axis] == 0.0 {
+            if min > 0.0 || max < 0.0 {
+                return None;
+            }
+            continue;
+        }
+
+        let (t0, t1) = (min / direction[axis], max / direction[axis]);
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+
+        if near > far {
+            return None;
+        }
+    }
+
+    Some(near)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hit {
+    Block(RayHit),
+    Player {
+        player_id: PlayerId,
+        kind: HitKind,
+        distance: f32,
+    },
+}
+
+impl Hit {
+    pub const fn distance(&self) -> f32 {
+        match self {
+            Self::Block(hit) => hit.distance,
+            Self::Player { distance, .. } => *distance,
+        }
+    }
+}
+
+/// Casts a shot from `origin` along `direction`, returning the first block or player hit.
+///
+/// The shooter itself should not be part of `targets`.
+pub fn hitscan<I>(world: &World, origin: Position, direction: Position, targets: I) -> Option<Hit>
+where
+    I: IntoIterator<Item = Target>,
+{
+    let length =
+        (direction.x * direction.x + direction.y * direction.y + direction.z * direction.z).sqrt();
+    if length == 0.0 {
+        return None;
+    }
+    let direction = Position::new_xyz(
+        direction.x / length,
+        direction.y / length,
+        direction.z / length,
+    );
+
+    let block = world.raycast(origin, direction, MAX_RANGE).map(Hit::Block);
+    let player = targets
+        .into_iter()
+        .filter_map(|target| {
+            target
+                .intersect(origin, direction)
+                .map(|(kind, distance)| Hit::Player {
+                    player_id: target.player_id,
+                    kind,
+                    distance,
+                })
+        })
+        .filter(|hit| hit.distance() <= MAX_RANGE)
+        .min_by(|a, b| a.distance().total_cmp(&b.distance()));
+
+    match (block, player) {
+        (Some(block), Some(player)) if player.distance() < block.distance() => Some(player),
+        (Some(block), _) => Some(block),
+        (None, player) => player,
+    }
+}
+
+/// Checks whether a reported hit on `kind` of `target` is possible for a shooter at `origin`
+/// looking along `orientation`.
+///
+/// The hitbox has to be within `max_angle` (radians) of the view direction, to account for
+/// weapon spread and latency, and there must be no block in between.
+pub fn is_hit_plausible(
+    world: &World,
+    origin: Position,
+    orientation: Position,
+    target: &Target,
+    kind: HitKind,
+    max_angle: f32,
+) -> bool {
+    let Some(center) = target.hitbox_center(kind) else {
+        return false;
+    };
+
+    let to = Position::new_xyz(
+        center.x - origin.x,
+        center.y - origin.y,
+        center.z - origin.z,
+    );
+    let distance = (to.x * to.x + to.y * to.y + to.z * to.z).sqrt();
+    let view = (orientation.x * orientation.x
+        + orientation.y * orientation.y
+        + orientation.z * orientation.z)
+        .sqrt();
+
+    if distance == 0.0 || view == 0.0 || distance > MAX_RANGE {
+        return false;
+    }
+
+    let cos =
+        (to.x * orientation.x + to.y * orientation.y + to.z * orientation.z) / (distance * view);
+
+    cos >= max_angle.cos() && world.can_see(origin, center)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{msg::model::BlockPosition, world::DEFAULT_COLOR};
+
+    #[test]
+    fn wall_blocks_shot() {
+        let mut world = World::new();
+        let target = Target {
+            player_id: PlayerId(2),
+            position: Position::new_xyz(20.5, 10.5, 40.0),
+            orientation: Position::new_xyz(-1.0, 0.0, 0.0),
+            crouching: false,
+        };
+        let origin = Position::new_xyz(10.5, 10.5, 40.0);
+        let forward = Position::new_xyz(1.0, 0.0, 0.0);
+
+        assert!(matches!(
+            hitscan(&world, origin, forward, [target]),
+            Some(Hit::Player {
+                kind: HitKind::Head,
+                ..
+            })
+        ));
+        assert!(is_hit_plausible(
+            &world,
+            origin,
+            forward,
+            &target,
+            HitKind::Legs,
+            0.3
+        ));
+
+        world.set(BlockPosition::new_xyz(15, 10, 40), Some(DEFAULT_COLOR));
+
+        assert!(matches!(
+            hitscan(&world, origin, forward, [target]),
+            Some(Hit::Block(_))
+        ));
+        assert!(!is_hit_plausible(
+            &world,
+            origin,
+            forward,
+            &target,
+            HitKind::Head,
+            0.3
+        ));
+    }
+}
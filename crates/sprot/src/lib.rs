@@ -36,5 +36,15 @@
 // - Create module/struct for strings
 // - Check boxing of messages in Msg enum
 
+pub mod connection;
 pub mod error;
+pub mod gamemode;
+pub mod grenade;
+pub mod hitscan;
+pub mod loadout;
 pub mod msg;
+pub mod physics;
+pub mod slots;
+pub mod state;
+pub mod weapon;
+pub mod world;
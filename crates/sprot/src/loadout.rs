@@ -0,0 +1,165 @@
+//! Team and weapon changes.
+//!
+//! Clients request a change with `ChangeTeam` or `ChangeWeapon`, which the server does not
+//! relay. A living player is killed with a `KillAction` of `KillKind::TeamChange` or
+//! `KillKind::ClassChange` instead, a weapon change is followed by the `ChangeWeapon`. The
+//! player comes back with a `CreatePlayer` carrying the new team and weapon.
+
+use crate::msg::{
+    model::{KillKind, PlayerId, Team, WeaponKind},
+    msg::{ChangeTeam, ChangeWeapon, KillAction, Msg},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadoutChange {
+    Team(Team),
+    Weapon(WeaponKind),
+}
+
+impl LoadoutChange {
+    /// The request a client sends for the change.
+    pub const fn request(self, player_id: PlayerId) -> Msg {
+        match self {
+            Self::Team(team) => Msg::ChangeTeam(ChangeTeam { player_id, team }),
+            Self::Weapon(kind) => Msg::ChangeWeapon(ChangeWeapon { player_id, kind }),
+        }
+    }
+
+    pub const fn kill_kind(self) -> KillKind {
+        match self {
+            Self::Team(_) => KillKind::TeamChange,
+            Self::Weapon(_) => KillKind::ClassChange,
+        }
+    }
+
+    /// The messages the server sends for the change of a player, before the `CreatePlayer`
+    /// after `respawn_time` seconds. Dead players are not killed again.
+    pub fn announcement(self, player_id: PlayerId, alive: bool, respawn_time: u8) -> Vec<Msg> {
+        let kill = alive.then_some(Msg::KillAction(KillAction {
+            player_id,
+            killer_id: player_id,
+            kind: self.kill_kind(),
+            respawn_time,
+        }));
+        let relay = match self {
+            Self::Team(_) => None,
+            Self::Weapon(kind) => Some(Msg::ChangeWeapon(ChangeWeapon { player_id, kind })),
+        };
+
+        kill.into_iter().chain(relay).collect()
+    }
+
+    /// Whether `msg` from the server completes the change of `player_id`.
+    pub fn is_completed_by(self, player_id: PlayerId, msg: &Msg) -> bool {
+        match (self, msg) {
+            (Self::Team(team), Msg::CreatePlayer(create)) => {
+                create.player_id == player_id && create.team == team
+            }
+            (Self::Weapon(kind), Msg::CreatePlayer(create)) => {
+                create.player_id == player_id && create.weapon == kind
+            }
+            (Self::Weapon(kind), Msg::ChangeWeapon(change)) => {
+                change.player_id == player_id && change.kind == kind
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A change requested by the local player, until the server carried it out.
+///
+/// Feed every message received from the server into [`PendingChange::update`]. The server may
+/// refuse a change, e.g. a team change that would unbalance the teams, so a change can stay
+/// pending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingChange {
+    player_id: PlayerId,
+    change: LoadoutChange,
+    killed: bool,
+}
+
+impl PendingChange {
+    pub const fn new(player_id: PlayerId, change: LoadoutChange) -> Self {
+        Self {
+            player_id,
+            change,
+            killed: false,
+        }
+    }
+
+    /// The message to send to the server.
+    pub const fn request(&self) -> Msg {
+        self.change.request(self.player_id)
+    }
+
+    pub const fn change(&self) -> LoadoutChange {
+        self.change
+    }
+
+    /// Whether the server killed the player for the change.
+    pub const fn is_killed(&self) -> bool {
+        self.killed
+    }
+
+    /// Returns `true` once `msg` completed the change.
+    pub fn update(&mut self, msg: &Msg) -> bool {
+        if let Msg::KillAction(kill) = msg {
+            if kill.player_id == self.player_id && kill.kind == self.change.kill_kind() {
+                self.killed = true;
+            }
+        }
+
+        self.change.is_completed_by(self.player_id, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::{model::Position, msg::CreatePlayer};
+
+    #[test]
+    fn request_and_announcement_agree() {
+        let player_id = PlayerId(5);
+        let change = LoadoutChange::Weapon(WeaponKind::Shotgun);
+        let mut pending = PendingChange::new(player_id, change);
+        assert_eq!(
+            pending.request(),
+            Msg::ChangeWeapon(ChangeWeapon {
+                player_id,
+                kind: WeaponKind::Shotgun
+            })
+        );
+
+        let announcement = change.announcement(player_id, true, 5);
+        assert!(matches!(
+            announcement[..],
+            [
+                Msg::KillAction(KillAction {
+                    kind: KillKind::ClassChange,
+                    ..
+                }),
+                Msg::ChangeWeapon(_)
+            ]
+        ));
+        assert!(!pending.update(&announcement[0]));
+        assert!(pending.is_killed());
+        assert!(pending.update(&announcement[1]));
+
+        let change = LoadoutChange::Team(Team::GREEN);
+        let mut pending = PendingChange::new(player_id, change);
+        assert!(change.announcement(player_id, false, 5).is_empty());
+
+        let mut create = CreatePlayer {
+            player_id,
+            weapon: WeaponKind::Rifle,
+            team: Team::BLUE,
+            position: Position::default(),
+            name: "Deuce".to_owned(),
+        };
+        assert!(!pending.update(&Msg::CreatePlayer(create.clone())));
+        create.team = Team::GREEN;
+        assert!(pending.update(&Msg::CreatePlayer(create)));
+        assert!(!pending.is_killed());
+    }
+}
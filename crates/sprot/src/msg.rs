@@ -1,6 +1,5 @@
 // TODO:
 // - Explicit CP437 Bytes/String parser
-// - Replace position from float to i32 int for BlockAction, BlockLine
 
 macro_rules! msgs {
     (
@@ -286,7 +285,7 @@ msgs! {
     /// Sent by the client when player changes weapon, and relayed to clients by server after filter_visibility logic is applied.
     /// Receiving clients will also be sent a preceding Kill Action to inform them the player has died both of which are sent as reliable packets.
     ///
-    /// Direction: `Client -> Server`
+    /// Direction: `Client <-> Server`
     30: ChangeWeapon @ = 3;
 
     /// <https://github.com/yvt/openspades/blob/40fe69fa9a5216511e1f700c75817bab66540db9/Sources/Client/NetClient.cpp#L1267>
@@ -420,6 +419,48 @@ pub mod model {
         pub const fn new_xyz(x: LEFloat, y: LEFloat, z: LEFloat) -> Self {
             Self { x, y, z }
         }
+
+        pub fn distance(self, other: Self) -> f32 {
+            let (x, y, z) = (self.x - other.x, self.y - other.y, self.z - other.z);
+
+            (x * x + y * y + z * z).sqrt()
+        }
+    }
+
+    /// Integer position of a single block/voxel, with the same axes as [`Position`].
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct BlockPosition {
+        pub x: i32,
+        pub y: i32,
+        pub z: i32,
+    }
+
+    impl BlockPosition {
+        pub const fn new_xyz(x: i32, y: i32, z: i32) -> Self {
+            Self { x, y, z }
+        }
+
+        /// The block containing `position`.
+        pub const fn from_position(position: Position) -> Self {
+            Self {
+                x: position.x.floor() as i32,
+                y: position.y.floor() as i32,
+                z: position.z.floor() as i32,
+            }
+        }
+
+        /// The center of the block.
+        pub fn to_position(self) -> Position {
+            Position::new_xyz(
+                self.x as f32 + 0.5,
+                self.y as f32 + 0.5,
+                self.z as f32 + 0.5,
+            )
+        }
+
+        pub const fn offset(self, x: i32, y: i32, z: i32) -> Self {
+            Self::new_xyz(self.x + x, self.y + y, self.z + z)
+        }
     }
 
     #[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -443,6 +484,24 @@ pub mod model {
         pub const GREEN: Self = Self(1);
         // From `Move Object`
         pub const NEUTRAL: Self = Self(2);
+
+        /// Index of a playing team (`0` for blue, `1` for green).
+        pub const fn index(self) -> Option<usize> {
+            match self {
+                Self::BLUE => Some(0),
+                Self::GREEN => Some(1),
+                _ => None,
+            }
+        }
+
+        /// The opposing team. Spectator and neutral map onto themselves.
+        pub const fn other(self) -> Self {
+            match self {
+                Self::BLUE => Self::GREEN,
+                Self::GREEN => Self::BLUE,
+                _ => self,
+            }
+        }
     }
 
     #[rustfmt::skip]
@@ -471,7 +530,7 @@ pub mod model {
 
     impl KeyInput {
         pub const fn is_active(self, key: InputKey) -> bool {
-            self.0 | key.to_mask() == 0b1
+            self.0 & key.to_mask() != 0
         }
 
         pub fn set_active(&mut self, key: InputKey) {
@@ -499,7 +558,7 @@ pub mod model {
 
     impl WeaponInput {
         pub const fn is_active(self, key: WeaponKey) -> bool {
-            self.0 | key.to_mask() == 0b1
+            self.0 & key.to_mask() != 0
         }
 
         pub fn set_active(&mut self, key: InputKey) {
@@ -545,6 +604,10 @@ pub mod model {
         pub const fn new_rgb(r: UByte, g: UByte, b: UByte) -> Self {
             Self { r, g, b }
         }
+
+        pub const fn to_rgb(self) -> [UByte; 3] {
+            [self.r, self.g, self.b]
+        }
     }
 
     byte_enum! {
@@ -577,6 +640,14 @@ pub mod model {
     pub struct IntelFlags(pub UByte);
 
     impl IntelFlags {
+        pub const fn new(team1: HoldState, team2: HoldState) -> Self {
+            Self(team1 as u8 | (team2 as u8) << 1)
+        }
+
+        pub const fn from_locations(team1: &IntelLocation, team2: &IntelLocation) -> Self {
+            Self::new(team1.hold_state(), team2.hold_state())
+        }
+
         pub fn team1_hold_state(&self) -> Result<HoldState, VariantError<u8>> {
             HoldState::try_from(self.0 & 0b01)
         }
@@ -584,6 +655,20 @@ pub mod model {
         pub fn team2_hold_state(&self) -> Result<HoldState, VariantError<u8>> {
             HoldState::try_from((self.0 & 0b10) >> 1)
         }
+
+        /// Hold state of the intel owned by `team`.
+        pub const fn hold_state(&self, team: Team) -> Option<HoldState> {
+            let shift = match team.index() {
+                Some(index) => index,
+                None => return None,
+            };
+
+            if (self.0 >> shift) & 0b1 == 1 {
+                Some(HoldState::Holding)
+            } else {
+                Some(HoldState::Dropped)
+            }
+        }
     }
 
     byte_enum! {
@@ -605,6 +690,22 @@ pub mod model {
         Dropped(Position),
     }
 
+    impl IntelLocation {
+        pub const fn hold_state(&self) -> HoldState {
+            match self {
+                Self::Held(_) => HoldState::Holding,
+                Self::Dropped(_) => HoldState::Dropped,
+            }
+        }
+
+        pub const fn holder(&self) -> Option<PlayerId> {
+            match self {
+                Self::Held(player_id) => Some(*player_id),
+                Self::Dropped(_) => None,
+            }
+        }
+    }
+
     byte_enum! {
         pub enum KillKind {
             /// WEAPON (body, limbs)
@@ -699,6 +800,18 @@ pub mod model {
                 revision,
             }
         }
+
+        pub const fn major(&self) -> i8 {
+            self.major
+        }
+
+        pub const fn minor(&self) -> i8 {
+            self.minor
+        }
+
+        pub const fn revision(&self) -> i8 {
+            self.revision
+        }
     }
 }
 
@@ -706,8 +819,8 @@ pub mod model {
 pub mod msg {
     use nom::{
         branch::alt,
-        bytes::complete::{tag, take},
-        combinator::{all_consuming, map, map_parser},
+        bytes::complete::tag,
+        combinator::{all_consuming, map},
         multi::{many0, many_m_n},
         number::complete::{le_f32, le_u32},
         sequence::{pair, preceded, tuple},
@@ -716,9 +829,9 @@ pub mod msg {
 
     use super::{
         model::{
-            ActionKind, CachedKind, CaptureKind, ChatKind, Color, DamageKind, GameMode, HitKind,
-            IntelFlags, IntelLocation, KeyInput, KillKind, PlayerId, PlayerPosition, Position,
-            Team, TerritoryData, ToolKind, Version, WeaponKind,
+            ActionKind, BlockPosition, CachedKind, CaptureKind, ChatKind, Color, DamageKind,
+            GameMode, HitKind, IntelFlags, IntelLocation, KeyInput, KillKind, PlayerId,
+            PlayerPosition, Position, Team, TerritoryData, ToolKind, Version, WeaponKind,
         },
         MessageKind,
     };
@@ -729,6 +842,16 @@ pub mod msg {
         fn parse(i: &[u8]) -> IResult<&[u8], Self>
         where
             Self: Sized;
+
+        /// Writes the message, starting with its id.
+        fn encode(&self, o: &mut Vec<u8>);
+
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut o = Vec::new();
+            self.encode(&mut o);
+
+            o
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -748,6 +871,11 @@ pub mod msg {
 
             Ok((i, Self { position }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::position(o, &self.position);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -767,6 +895,11 @@ pub mod msg {
 
             Ok((i, Self { position }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::position(o, &self.position);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -800,6 +933,13 @@ pub mod msg {
 
             Ok((i, Self { player_positions }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            for player_position in &self.player_positions {
+                super::encode::player_position(o, player_position);
+            }
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -822,6 +962,14 @@ pub mod msg {
 
             Ok((i, Self { player_positions }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            for (player_id, player_position) in &self.player_positions {
+                super::encode::player_id(o, *player_id);
+                super::encode::player_position(o, player_position);
+            }
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -843,6 +991,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, state }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.state.0);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -864,6 +1018,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, state }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.state.0);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -885,6 +1045,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, kind }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -919,6 +1085,12 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend([self.hp, self.damage_kind as u8]);
+            super::encode::position(o, &self.source_position);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -957,6 +1129,14 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.extend(self.fuse_length.to_le_bytes());
+            super::encode::position(o, &self.position);
+            super::encode::position(o, &self.velocity);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -978,6 +1158,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, kind }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -999,6 +1185,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, color }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::color(o, self.color);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1046,6 +1238,16 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::team(o, self.team);
+            o.extend([self.weapon as u8, self.held_item as u8]);
+            o.extend(self.kills.to_le_bytes());
+            super::encode::color(o, self.color);
+            super::encode::string(o, &self.name);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1080,6 +1282,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::team(o, self.team);
+            o.push(self.weapon as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -1114,6 +1323,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::team(o, self.team);
+            super::encode::position(o, &self.position);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1154,13 +1370,22 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.weapon as u8);
+            super::encode::team(o, self.team);
+            super::encode::position(o, &self.position);
+            super::encode::string(o, &self.name);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
     pub struct BlockAction {
         pub player_id: PlayerId,
         pub kind: ActionKind,
-        pub position: Position,
+        pub position: BlockPosition,
     }
 
     impl Message for BlockAction {
@@ -1173,7 +1398,7 @@ pub mod msg {
             let inner = tuple((
                 super::parse::player_id,
                 super::parse::action_kind,
-                super::parse::position,
+                super::parse::block_position,
             ));
 
             let (i, (player_id, kind, position)) =
@@ -1188,13 +1413,20 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+            super::encode::block_position(o, &self.position);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
     pub struct BlockLine {
         pub player_id: PlayerId,
-        pub start: Position,
-        pub end: Position,
+        pub start: BlockPosition,
+        pub end: BlockPosition,
     }
 
     impl Message for BlockLine {
@@ -1206,8 +1438,8 @@ pub mod msg {
         {
             let inner = tuple((
                 super::parse::player_id,
-                super::parse::position,
-                super::parse::position,
+                super::parse::block_position,
+                super::parse::block_position,
             ));
 
             let (i, (player_id, start, end)) =
@@ -1222,6 +1454,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::block_position(o, &self.start);
+            super::encode::block_position(o, &self.end);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1250,8 +1489,8 @@ pub mod msg {
                 super::parse::color,
                 super::parse::color,
                 super::parse::color,
-                map_parser(take(10usize), super::parse::string),
-                map_parser(take(10usize), super::parse::string),
+                super::parse::fixed_string(10),
+                super::parse::fixed_string(10),
                 super::parse::gamemode,
             ));
 
@@ -1276,6 +1515,21 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::color(o, self.fog_color);
+            super::encode::color(o, self.team1_color);
+            super::encode::color(o, self.team2_color);
+            super::encode::fixed_string(o, &self.team1_name, 10);
+            super::encode::fixed_string(o, &self.team2_name, 10);
+            o.push(self.gamemode as u8);
+
+            if let Some(addition) = &self.addition {
+                addition.encode(o);
+            }
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -1291,7 +1545,7 @@ pub mod msg {
     }
 
     impl CTFState {
-        fn parse(i: &[u8]) -> IResult<&[u8], Self> {
+        pub fn parse(i: &[u8]) -> IResult<&[u8], Self> {
             let (i, (team1_score, team2_score, capture_limit, intel_flags)) = tuple((
                 super::parse::next(),
                 super::parse::next(),
@@ -1321,6 +1575,20 @@ pub mod msg {
                 },
             ))
         }
+
+        /// The intel flags are derived from the intel locations, as the layout of the location
+        /// data depends on them.
+        pub fn encode(&self, o: &mut Vec<u8>) {
+            o.extend([self.team1_score, self.team2_score, self.capture_limit]);
+            super::encode::intel_flags(
+                o,
+                IntelFlags::from_locations(&self.team1_intel_location, &self.team2_intel_location),
+            );
+            super::encode::intel_location(o, &self.team1_intel_location);
+            super::encode::intel_location(o, &self.team2_intel_location);
+            super::encode::position(o, &self.team1_base);
+            super::encode::position(o, &self.team2_base);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1330,7 +1598,7 @@ pub mod msg {
     }
 
     impl TCState {
-        fn parse(i: &[u8]) -> IResult<&[u8], Self> {
+        pub fn parse(i: &[u8]) -> IResult<&[u8], Self> {
             let (i, territory_count) = super::parse::next()(i)?;
             let (i, territory_data) = many_m_n(
                 territory_count as usize,
@@ -1346,6 +1614,17 @@ pub mod msg {
                 },
             ))
         }
+
+        /// The territory count is taken from the territory data, territories beyond the 255
+        /// the count byte holds are left out.
+        pub fn encode(&self, o: &mut Vec<u8>) {
+            let count = self.territory_data.len().min(usize::from(u8::MAX));
+            o.push(count as u8);
+
+            for territory in &self.territory_data[..count] {
+                super::encode::territory_data(o, territory);
+            }
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1373,6 +1652,20 @@ pub mod msg {
                 }
             }
         }
+
+        pub const fn gamemode(&self) -> GameMode {
+            match self {
+                Self::CTFState(_) => GameMode::CTF,
+                Self::TCState(_) => GameMode::TC,
+            }
+        }
+
+        pub fn encode(&self, o: &mut Vec<u8>) {
+            match self {
+                Self::CTFState(ctf) => ctf.encode(o),
+                Self::TCState(tc) => tc.encode(o),
+            }
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1410,6 +1703,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::player_id(o, self.killer_id);
+            o.extend([self.kind as u8, self.respawn_time]);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1444,6 +1744,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+            super::encode::string(o, &self.message);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1462,6 +1769,11 @@ pub mod msg {
 
             Ok((i, Self { size }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend(self.size.to_le_bytes());
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1484,6 +1796,13 @@ pub mod msg {
 
             Ok((i, Self { size, crc, name }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend(self.size.to_le_bytes());
+            o.extend(self.crc.to_le_bytes());
+            super::encode::string(o, &self.name);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1505,6 +1824,11 @@ pub mod msg {
 
             Ok((&[], Self { data: i.to_vec() }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend(&self.data);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1526,6 +1850,11 @@ pub mod msg {
 
             Ok((i, Self { player_id }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1563,6 +1892,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.extend([self.entity_id, self.kind as u8]);
+            super::encode::team(o, self.team);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -1600,6 +1936,14 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.push(self.entity_id);
+            super::encode::team(o, self.capturing_team);
+            o.push(self.rate as u8);
+            o.extend(self.progress.to_le_bytes());
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1621,6 +1965,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, kind }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1642,6 +1992,11 @@ pub mod msg {
 
             Ok((i, Self { player_id }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -1670,6 +2025,12 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::position(o, &self.position);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1691,6 +2052,11 @@ pub mod msg {
 
             Ok((i, Self { player_id }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1699,7 +2065,7 @@ pub mod msg {
     }
 
     impl Message for FogColor {
-        const KIND: MessageKind = MessageKind::Restock;
+        const KIND: MessageKind = MessageKind::FogColor;
 
         fn parse(i: &[u8]) -> IResult<&[u8], Self>
         where
@@ -1712,6 +2078,11 @@ pub mod msg {
 
             Ok((i, Self { color }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::fog_color(o, self.color);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1746,6 +2117,12 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.extend([self.clip_ammo, self.reserve_ammo]);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1755,7 +2132,7 @@ pub mod msg {
     }
 
     impl Message for ChangeTeam {
-        const KIND: MessageKind = MessageKind::WeaponReload;
+        const KIND: MessageKind = MessageKind::ChangeTeam;
 
         fn parse(i: &[u8]) -> IResult<&[u8], Self>
         where
@@ -1767,6 +2144,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, team }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            super::encode::team(o, self.team);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1788,6 +2171,12 @@ pub mod msg {
 
             Ok((i, Self { player_id, kind }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            super::encode::player_id(o, self.player_id);
+            o.push(self.kind as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1809,6 +2198,11 @@ pub mod msg {
 
             Ok((i, Self { kind }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.push(self.kind as u8);
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1827,6 +2221,11 @@ pub mod msg {
 
             Ok((i, Self { challenge }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend(self.challenge.to_le_bytes());
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1845,6 +2244,11 @@ pub mod msg {
 
             Ok((i, Self { challenge }))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.extend(self.challenge.to_le_bytes());
+        }
     }
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -1857,8 +2261,14 @@ pub mod msg {
         where
             Self: Sized,
         {
+            let (i, _) = tag(&[<Self as Message>::KIND.id()])(i)?;
+
             Ok((i, Self))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1893,6 +2303,13 @@ pub mod msg {
                 },
             ))
         }
+
+        fn encode(&self, o: &mut Vec<u8>) {
+            o.push(<Self as Message>::KIND.id());
+            o.push(self.client_identifier as u8);
+            super::encode::version(o, &self.version);
+            super::encode::string(o, &self.name);
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -1976,6 +2393,7 @@ pub mod msg {
                     map(Restock::parse, Self::Restock),
                     map(FogColor::parse, Self::FogColor),
                     map(WeaponReload::parse, Self::WeaponReload),
+                    map(ChangeWeapon::parse, Self::ChangeWeapon),
                     map(VersionHandshakeInit::parse, Self::VersionHandshakeInit),
                     map(VersionGet::parse, Self::VersionGet),
                 )),
@@ -1984,21 +2402,145 @@ pub mod msg {
 
             Ok(msg)
         }
+
+        /// Parses a message sent by a client.
+        #[rustfmt::skip]
+        pub fn parse_client(i: &[u8]) -> Result<Self, nom::error::Error<&[u8]>> {
+            let (_, msg) = all_consuming(alt((
+                map(PositionData::parse, Self::PositionData),
+                map(OrientationData::parse, Self::OrientationData),
+                map(InputData::parse, Self::InputData),
+                map(WeaponInput::parse, Self::WeaponInput),
+                map(HitPacket::parse, Self::HitPacket),
+                map(GrenadePacket::parse, Self::GrenadePacket),
+                map(SetTool::parse, Self::SetTool),
+                map(SetColor::parse, Self::SetColor),
+                map(ExisitingPlayer::parse, Self::ExisitingPlayer),
+                map(ShortPlayerData::parse, Self::ShortPlayerData),
+                map(BlockAction::parse, Self::BlockAction),
+                map(BlockLine::parse, Self::BlockLine),
+                map(ChatMessage::parse, Self::ChatMessage),
+                map(WeaponReload::parse, Self::WeaponReload),
+                map(ChangeTeam::parse, Self::ChangeTeam),
+                map(ChangeWeapon::parse, Self::ChangeWeapon),
+                map(MapCached::parse, Self::MapCached),
+                map(VersionHandshakeResponse::parse, Self::VersionHandshakeResponse),
+                map(VersionResponse::parse, Self::VersionResponse),
+            )))(i)
+            .finish()?;
+
+            Ok(msg)
+        }
+
+        pub const fn kind(&self) -> MessageKind {
+            match self {
+                Self::PositionData(_) => PositionData::KIND,
+                Self::OrientationData(_) => OrientationData::KIND,
+                Self::WorldUpdate75(_) => WorldUpdate75::KIND,
+                Self::WorldUpdate76(_) => WorldUpdate76::KIND,
+                Self::InputData(_) => InputData::KIND,
+                Self::WeaponInput(_) => WeaponInput::KIND,
+                Self::HitPacket(_) => HitPacket::KIND,
+                Self::SetHP(_) => SetHP::KIND,
+                Self::GrenadePacket(_) => GrenadePacket::KIND,
+                Self::SetTool(_) => SetTool::KIND,
+                Self::SetColor(_) => SetColor::KIND,
+                Self::ExisitingPlayer(_) => ExisitingPlayer::KIND,
+                Self::ShortPlayerData(_) => ShortPlayerData::KIND,
+                Self::MoveObject(_) => MoveObject::KIND,
+                Self::CreatePlayer(_) => CreatePlayer::KIND,
+                Self::BlockAction(_) => BlockAction::KIND,
+                Self::BlockLine(_) => BlockLine::KIND,
+                Self::StateData(_) => StateData::KIND,
+                Self::KillAction(_) => KillAction::KIND,
+                Self::ChatMessage(_) => ChatMessage::KIND,
+                Self::MapStart75(_) => MapStart75::KIND,
+                Self::MapStart76(_) => MapStart76::KIND,
+                Self::MapChunk(_) => MapChunk::KIND,
+                Self::PlayerLeft(_) => PlayerLeft::KIND,
+                Self::TerritoryCapture(_) => TerritoryCapture::KIND,
+                Self::ProgressBar(_) => ProgressBar::KIND,
+                Self::IntelCapture(_) => IntelCapture::KIND,
+                Self::IntelPickup(_) => IntelPickup::KIND,
+                Self::IntelDrop(_) => IntelDrop::KIND,
+                Self::Restock(_) => Restock::KIND,
+                Self::FogColor(_) => FogColor::KIND,
+                Self::WeaponReload(_) => WeaponReload::KIND,
+                Self::ChangeTeam(_) => ChangeTeam::KIND,
+                Self::ChangeWeapon(_) => ChangeWeapon::KIND,
+                Self::MapCached(_) => MapCached::KIND,
+                Self::VersionHandshakeInit(_) => VersionHandshakeInit::KIND,
+                Self::VersionHandshakeResponse(_) => VersionHandshakeResponse::KIND,
+                Self::VersionGet(_) => VersionGet::KIND,
+                Self::VersionResponse(_) => VersionResponse::KIND,
+            }
+        }
+
+        pub fn encode(&self, o: &mut Vec<u8>) {
+            match self {
+                Self::PositionData(msg) => msg.encode(o),
+                Self::OrientationData(msg) => msg.encode(o),
+                Self::WorldUpdate75(msg) => msg.encode(o),
+                Self::WorldUpdate76(msg) => msg.encode(o),
+                Self::InputData(msg) => msg.encode(o),
+                Self::WeaponInput(msg) => msg.encode(o),
+                Self::HitPacket(msg) => msg.encode(o),
+                Self::SetHP(msg) => msg.encode(o),
+                Self::GrenadePacket(msg) => msg.encode(o),
+                Self::SetTool(msg) => msg.encode(o),
+                Self::SetColor(msg) => msg.encode(o),
+                Self::ExisitingPlayer(msg) => msg.encode(o),
+                Self::ShortPlayerData(msg) => msg.encode(o),
+                Self::MoveObject(msg) => msg.encode(o),
+                Self::CreatePlayer(msg) => msg.encode(o),
+                Self::BlockAction(msg) => msg.encode(o),
+                Self::BlockLine(msg) => msg.encode(o),
+                Self::StateData(msg) => msg.encode(o),
+                Self::KillAction(msg) => msg.encode(o),
+                Self::ChatMessage(msg) => msg.encode(o),
+                Self::MapStart75(msg) => msg.encode(o),
+                Self::MapStart76(msg) => msg.encode(o),
+                Self::MapChunk(msg) => msg.encode(o),
+                Self::PlayerLeft(msg) => msg.encode(o),
+                Self::TerritoryCapture(msg) => msg.encode(o),
+                Self::ProgressBar(msg) => msg.encode(o),
+                Self::IntelCapture(msg) => msg.encode(o),
+                Self::IntelPickup(msg) => msg.encode(o),
+                Self::IntelDrop(msg) => msg.encode(o),
+                Self::Restock(msg) => msg.encode(o),
+                Self::FogColor(msg) => msg.encode(o),
+                Self::WeaponReload(msg) => msg.encode(o),
+                Self::ChangeTeam(msg) => msg.encode(o),
+                Self::ChangeWeapon(msg) => msg.encode(o),
+                Self::MapCached(msg) => msg.encode(o),
+                Self::VersionHandshakeInit(msg) => msg.encode(o),
+                Self::VersionHandshakeResponse(msg) => msg.encode(o),
+                Self::VersionGet(msg) => msg.encode(o),
+                Self::VersionResponse(msg) => msg.encode(o),
+            }
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut o = Vec::new();
+            self.encode(&mut o);
+
+            o
+        }
     }
 }
 
 pub mod parse {
     use nom::{
         bytes::complete::take,
-        combinator::{map, map_res},
-        number::complete::le_f32,
+        combinator::{map, map_parser, map_res},
+        number::complete::{le_f32, le_i32},
         sequence::{pair, terminated, tuple},
         IResult,
     };
 
     use super::model::{
-        ActionKind, CachedKind, CaptureKind, ChatKind, Color, DamageKind, FogColor, GameMode,
-        HitKind, HoldState, IntelFlags, IntelLocation, KeyInput, KillKind, PlayerId,
+        ActionKind, BlockPosition, CachedKind, CaptureKind, ChatKind, Color, DamageKind, FogColor,
+        GameMode, HitKind, HoldState, IntelFlags, IntelLocation, KeyInput, KillKind, PlayerId,
         PlayerPosition, Position, Team, TerritoryData, ToolKind, Version, WeaponInput, WeaponKind,
     };
 
@@ -2015,20 +2557,29 @@ pub mod parse {
 
     // Discards a trailing 0 (c style strings).
     pub fn str(i: &[u8]) -> IResult<&[u8], &str> {
-        // TODO: Remove unwrap
-        let s = if i.last() == Some(&b'\0') {
-            std::str::from_utf8(&i[..i.len() - 1]).unwrap()
-        } else {
-            std::str::from_utf8(i).unwrap()
-        };
+        let bytes = i.strip_suffix(b"\0").unwrap_or(i);
 
-        Ok((&[], s))
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Ok((&[], s)),
+            Err(_) => Err(nom::Err::Error(nom::error::Error::new(
+                i,
+                nom::error::ErrorKind::Char,
+            ))),
+        }
     }
 
     pub fn string(i: &[u8]) -> IResult<&[u8], String> {
         map(str, |s: &str| s.to_owned())(i)
     }
 
+    /// A string padded with zeros to `len` bytes.
+    pub fn fixed_string<'a>(len: usize) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], String> {
+        map_parser(
+            take(len),
+            map(str, |s: &str| s.trim_end_matches('\0').to_owned()),
+        )
+    }
+
     pub fn player_id(i: &[u8]) -> IResult<&[u8], PlayerId> {
         map(next(), PlayerId)(i)
     }
@@ -2039,6 +2590,12 @@ pub mod parse {
         Ok((i, Position::new_xyz(x, y, z)))
     }
 
+    pub fn block_position(i: &[u8]) -> IResult<&[u8], BlockPosition> {
+        let (i, (x, y, z)) = tuple((le_i32, le_i32, le_i32))(i)?;
+
+        Ok((i, BlockPosition::new_xyz(x, y, z)))
+    }
+
     pub fn color(i: &[u8]) -> IResult<&[u8], Color> {
         let (i, (b, g, r)) = tuple((next(), next(), next()))(i)?;
 
@@ -2149,6 +2706,94 @@ pub mod parse {
     }
 }
 
+pub mod encode {
+    use super::model::{
+        BlockPosition, Color, FogColor, IntelFlags, IntelLocation, PlayerId, PlayerPosition,
+        Position, Team, TerritoryData, Version,
+    };
+
+    pub fn player_id(o: &mut Vec<u8>, player_id: PlayerId) {
+        o.push(player_id.0);
+    }
+
+    /// Terminated with a 0 (c style strings).
+    pub fn string(o: &mut Vec<u8>, s: &str) {
+        o.extend(s.as_bytes());
+        o.push(0);
+    }
+
+    /// Truncates or pads the string with zeros to `len` bytes, truncating only between
+    /// characters.
+    pub fn fixed_string(o: &mut Vec<u8>, s: &str, len: usize) {
+        let mut end = s.len().min(len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        o.extend(&s.as_bytes()[..end]);
+        o.resize(o.len() + len - end, 0);
+    }
+
+    pub fn position(o: &mut Vec<u8>, position: &Position) {
+        o.extend(position.x.to_le_bytes());
+        o.extend(position.y.to_le_bytes());
+        o.extend(position.z.to_le_bytes());
+    }
+
+    pub fn block_position(o: &mut Vec<u8>, position: &BlockPosition) {
+        o.extend(position.x.to_le_bytes());
+        o.extend(position.y.to_le_bytes());
+        o.extend(position.z.to_le_bytes());
+    }
+
+    pub fn player_position(o: &mut Vec<u8>, player_position: &PlayerPosition) {
+        position(o, &player_position.position);
+        position(o, &player_position.orientation);
+    }
+
+    pub fn color(o: &mut Vec<u8>, color: Color) {
+        let [r, g, b] = color.to_rgb();
+
+        o.extend([b, g, r]);
+    }
+
+    pub fn fog_color(o: &mut Vec<u8>, fog_color: FogColor) {
+        color(o, Color::new_rgb(fog_color.r, fog_color.g, fog_color.b));
+        o.push(fog_color.a);
+    }
+
+    pub fn version(o: &mut Vec<u8>, version: &Version) {
+        o.extend([
+            version.major() as u8,
+            version.minor() as u8,
+            version.revision() as u8,
+        ]);
+    }
+
+    pub fn team(o: &mut Vec<u8>, team: Team) {
+        o.push(team.0 as u8);
+    }
+
+    pub fn intel_flags(o: &mut Vec<u8>, flags: IntelFlags) {
+        o.push(flags.0);
+    }
+
+    pub fn intel_location(o: &mut Vec<u8>, location: &IntelLocation) {
+        match location {
+            IntelLocation::Held(holder) => {
+                player_id(o, *holder);
+                o.extend([0; 11]);
+            }
+            IntelLocation::Dropped(at) => position(o, at),
+        }
+    }
+
+    pub fn territory_data(o: &mut Vec<u8>, territory: &TerritoryData) {
+        position(o, &territory.position);
+        team(o, territory.owner_team);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2160,4 +2805,113 @@ mod tests {
         assert_eq!(t.id(), 30);
         assert_eq!(t.size(), MessageSize::Exact(3));
     }
+
+    #[test]
+    fn ctf_state_roundtrip() {
+        use model::{IntelFlags, IntelLocation, PlayerId, Position};
+        use msg::CTFState;
+
+        let state = CTFState {
+            team1_score: 2,
+            team2_score: 5,
+            capture_limit: 10,
+            intel_flags: IntelFlags(0b10),
+            team1_intel_location: IntelLocation::Dropped(Position::new_xyz(1.0, 2.0, 3.0)),
+            team2_intel_location: IntelLocation::Held(PlayerId(7)),
+            team1_base: Position::new_xyz(64.0, 256.0, 40.0),
+            team2_base: Position::new_xyz(448.0, 256.0, 40.0),
+        };
+
+        let mut o = Vec::new();
+        state.encode(&mut o);
+
+        assert_eq!(o.len(), 52);
+        assert_eq!(CTFState::parse(&o), Ok((&[][..], state)));
+    }
+
+    #[test]
+    fn fixed_strings() {
+        let mut o = Vec::new();
+        encode::fixed_string(&mut o, "Blue", 6);
+        // The 'ü' would be cut in half.
+        encode::fixed_string(&mut o, "Grün", 3);
+
+        assert_eq!(o, b"Blue\0\0Gr\0");
+    }
+
+    #[test]
+    fn tc_state_roundtrip() {
+        use model::{Position, Team, TerritoryData};
+        use msg::TCState;
+
+        let state = TCState {
+            territory_count: 2,
+            territory_data: vec![
+                TerritoryData {
+                    position: Position::new_xyz(36.5, 256.5, 40.0),
+                    owner_team: Team::BLUE,
+                },
+                TerritoryData {
+                    position: Position::new_xyz(256.5, 128.5, 50.0),
+                    owner_team: Team::NEUTRAL,
+                },
+            ],
+        };
+
+        let mut o = Vec::new();
+        state.encode(&mut o);
+
+        assert_eq!(o.len(), 27);
+        assert_eq!(TCState::parse(&o), Ok((&[][..], state.clone())));
+
+        let many = TCState {
+            territory_count: 0,
+            territory_data: state.territory_data.repeat(150),
+        };
+        let mut o = Vec::new();
+        many.encode(&mut o);
+
+        let (_, parsed) = TCState::parse(&o).unwrap();
+        assert_eq!(parsed.territory_count, u8::MAX);
+        assert_eq!(parsed.territory_data[..], many.territory_data[..255]);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        use model::{ChatKind, Color, GameMode, PlayerId, Team};
+        use msg::{ChangeTeam, ChatMessage, Message, Msg, StateData, VersionGet};
+
+        let state_data = StateData {
+            player_id: PlayerId(3),
+            fog_color: Color::new_rgb(128, 232, 255),
+            team1_color: Color::new_rgb(0, 0, 255),
+            team2_color: Color::new_rgb(0, 255, 0),
+            team1_name: "Blue".to_owned(),
+            team2_name: "Green".to_owned(),
+            gamemode: GameMode::TC,
+            addition: None,
+        };
+        let bytes = state_data.to_bytes();
+
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(Msg::parse_server(&bytes), Ok(Msg::StateData(state_data)));
+
+        let chat = Msg::ChatMessage(ChatMessage {
+            player_id: PlayerId(1),
+            kind: ChatKind::Team,
+            message: "Hello".to_owned(),
+        });
+        assert_eq!(Msg::parse_client(&chat.to_bytes()), Ok(chat));
+
+        let change_team = Msg::ChangeTeam(ChangeTeam {
+            player_id: PlayerId(1),
+            team: Team::GREEN,
+        });
+        assert_eq!(Msg::parse_client(&change_team.to_bytes()), Ok(change_team));
+
+        assert_eq!(
+            Msg::parse_server(&VersionGet.to_bytes()),
+            Ok(Msg::VersionGet(VersionGet))
+        );
+    }
 }
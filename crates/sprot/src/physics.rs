@@ -0,0 +1,591 @@
+//! Player movement, ported from the physics of the original game (`world.c` of pysnip and the
+//! voxlap client).
+//!
+//! Units are blocks and seconds, velocities are scaled by 32 when applied (`fsynctics * 32`).
+
+use crate::{
+    msg::model::{BlockPosition, InputKey, KeyInput, Position, ToolKind, WeaponInput, WeaponKey},
+    world::World,
+};
+
+/// Rate at which the game steps the physics.
+pub const TICK_RATE: u32 = 60;
+
+/// Duration of a single physics step in seconds.
+pub const TICK: f32 = 1.0 / TICK_RATE as f32;
+
+pub const JUMP_VELOCITY: f32 = -0.36;
+
+/// Players land softly (without slowing down) below this vertical velocity.
+pub const FALL_SLOW_DOWN: f32 = 0.24;
+
+/// Landing faster than this hurts the player.
+pub const FALL_DAMAGE_VELOCITY: f32 = 0.58;
+
+pub const FALL_DAMAGE_SCALAR: f32 = 4096.0;
+
+/// Vertical distance between the eyes of a standing and a crouching player.
+const CROUCH_OFFSET: f32 = 0.9;
+
+/// Whether a point collides with the map, as seen by the player physics.
+///
+/// Everything outside of the map is solid, except above it.
+pub fn clipbox(world: &World, x: f32, y: f32, z: f32) -> bool {
+    if !(0.0..512.0).contains(&x) || !(0.0..512.0).contains(&y) {
+        return true;
+    }
+
+    if z < 0.0 {
+        return false;
+    }
+
+    let z = match z as i32 {
+        63 => 62,
+        z if z >= 64 => return true,
+        z => z,
+    };
+
+    world.is_solid(BlockPosition::new_xyz(x as i32, y as i32, z))
+}
+
+/// A landing hard enough to slow the player down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Landing {
+    /// Vertical velocity right before touching the ground.
+    pub velocity: f32,
+}
+
+impl Landing {
+    /// Health the player loses, growing with the square of the velocity above
+    /// [`FALL_DAMAGE_VELOCITY`].
+    pub fn damage(&self) -> u8 {
+        if self.velocity <= FALL_DAMAGE_VELOCITY {
+            return 0;
+        }
+
+        let excess = self.velocity - FALL_DAMAGE_VELOCITY;
+        (excess * excess * FALL_DAMAGE_SCALAR).min(f32::from(u8::MAX)) as u8
+    }
+}
+
+/// The physical state of a player.
+///
+/// `position` is the eye position of the player, as sent by `PositionData` and `WorldUpdate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerPhysics {
+    pub position: Position,
+    pub velocity: Position,
+    input: KeyInput,
+    weapon_input: WeaponInput,
+    tool: ToolKind,
+    forward: Position,
+    strafe: Position,
+    airborne: bool,
+    wade: bool,
+    time: f32,
+    last_climb: f32,
+}
+
+impl PlayerPhysics {
+    pub fn new(position: Position, orientation: Position) -> Self {
+        let mut physics = Self {
+            position,
+            velocity: Position::default(),
+            input: KeyInput(0),
+            weapon_input: WeaponInput(0),
+            tool: ToolKind::Gun,
+            forward: Position::default(),
+            strafe: Position::default(),
+            airborne: false,
+            wade: false,
+            time: 0.0,
+            last_climb: 0.0,
+        };
+        physics.set_orientation(orientation);
+
+        physics
+    }
+
+    pub const fn orientation(&self) -> Position {
+        self.forward
+    }
+
+    pub const fn input(&self) -> KeyInput {
+        self.input
+    }
+
+    pub const fn is_airborne(&self) -> bool {
+        self.airborne
+    }
+
+    /// Whether the player stands in the water.
+    pub const fn is_wading(&self) -> bool {
+        self.wade
+    }
+
+    /// Time of the last step the player climbed a block.
+    pub const fn last_climb(&self) -> f32 {
+        self.last_climb
+    }
+
+    pub const fn is_crouching(&self) -> bool {
+        self.input.is_active(InputKey::Crouch)
+    }
+
+    pub fn set_orientation(&mut self, orientation: Position) {
+        let length = (orientation.x * orientation.x + orientation.y * orientation.y).sqrt();
+
+        self.forward = orientation;
+        self.strafe = if length > 0.0 {
+            Position::new_xyz(-orientation.y / length, orientation.x / length, 0.0)
+        } else {
+            Position::default()
+        };
+    }
+
+    pub const fn set_weapon_input(&mut self, input: WeaponInput) {
+        self.weapon_input = input;
+    }
+
+    /// Sets the held tool, aiming down the sights of the gun slows the player down.
+    pub const fn set_tool(&mut self, tool: ToolKind) {
+        self.tool = tool;
+    }
+
+    /// Sets the pressed keys, crouching moves the eyes of the player down.
+    ///
+    /// Standing up is refused if there is no room above the player.
+    pub fn set_input(&mut self, world: &World, mut input: KeyInput) {
+        let crouch = input.is_active(InputKey::Crouch);
+
+        if crouch != self.is_crouching() {
+            if crouch {
+                if !self.airborne {
+                    self.position.z += CROUCH_OFFSET;
+                }
+            } else if self.can_stand_up(world) {
+                if !self.airborne {
+                    self.position.z -= CROUCH_OFFSET;
+                }
+            } else {
+                input.set_active(InputKey::Crouch);
+            }
+        }
+
+        self.input = input;
+    }
+
+    /// Advances the player by `dt` seconds.
+    ///
+    /// Returns the landing if the player hit the ground hard enough to slow down.
+    pub fn step(&mut self, world: &World, dt: f32) -> Option<Landing> {
+        self.time += dt;
+
+        if self.input.is_active(InputKey::Jump) && !self.airborne {
+            self.velocity.z = JUMP_VELOCITY;
+        }
+
+        let mut f = dt;
+        if self.airborne {
+            f *= 0.1;
+        } else if self.is_crouching() {
+            f *= 0.3;
+        } else if (self.weapon_input.is_active(WeaponKey::Secondary) && self.tool == ToolKind::Gun)
+            || self.input.is_active(InputKey::Sneak)
+        {
+            f *= 0.5;
+        } else if self.input.is_active(InputKey::Sprint) {
+            f *= 1.3;
+        }
+
+        let up = self.input.is_active(InputKey::Up);
+        let down = self.input.is_active(InputKey::Down);
+        let left = self.input.is_active(InputKey::Left);
+        let right = self.input.is_active(InputKey::Right);
+
+        // Limit the diagonal velocity when strafing and moving forwards/backwards.
+        if (up || down) && (left || right) {
+            f *= 0.5f32.sqrt();
+        }
+
+        if up {
+            self.velocity.x += self.forward.x * f;
+            self.velocity.y += self.forward.y * f;
+        } else if down {
+            self.velocity.x -= self.forward.x * f;
+            self.velocity.y -= self.forward.y * f;
+        }
+
+        if left {
+            self.velocity.x -= self.strafe.x * f;
+            self.velocity.y -= self.strafe.y * f;
+        } else if right {
+            self.velocity.x += self.strafe.x * f;
+            self.velocity.y += self.strafe.y * f;
+        }
+
+        // Gravity and air friction.
+        self.velocity.z = (self.velocity.z + dt) / (dt + 1.0);
+
+        let friction = if self.wade {
+            dt * 6.0 + 1.0
+        } else if !self.airborne {
+            dt * 4.0 + 1.0
+        } else {
+            dt + 1.0
+        };
+        self.velocity.x /= friction;
+        self.velocity.y /= friction;
+
+        let fall_velocity = self.velocity.z;
+        self.box_clip_move(world, dt);
+
+        if self.velocity.z == 0.0 && fall_velocity > FALL_SLOW_DOWN {
+            self.velocity.x *= 0.5;
+            self.velocity.y *= 0.5;
+
+            return Some(Landing {
+                velocity: fall_velocity,
+            });
+        }
+
+        None
+    }
+
+    fn can_stand_up(&self, world: &World) -> bool {
+        let Position { x, y, z } = self.position;
+
+        [(-0.45, -0.45), (-0.45, 0.45), (0.45, -0.45), (0.45, 0.45)]
+            .into_iter()
+            .all(|(dx, dy)| !clipbox(world, x + dx, y + dy, z - CROUCH_OFFSET - 0.45))
+    }
+
+    /// Moves the player by its velocity, clipping against the map and climbing single blocks.
+    fn box_clip_move(&mut self, world: &World, dt: f32) {
+        let clip = |x: f32, y: f32, z: f32| clipbox(world, x, y, z);
+
+        let f = dt * 32.0;
+        let nx = f * self.velocity.x + self.position.x;
+        let ny = f * self.velocity.y + self.position.y;
+
+        let crouch = self.is_crouching();
+        let (offset, mut m) = if crouch { (0.45, 0.9) } else { (0.9, 1.35) };
+        let mut nz = self.position.z + offset;
+
+        // Climbing is only possible while walking upright and not looking up steeply.
+        let can_climb = !crouch && self.forward.z < 0.5 && !self.input.is_active(InputKey::Sprint);
+        let mut climb = false;
+
+        let side = if self.velocity.x < 0.0 { -0.45 } else { 0.45 };
+        let blocked = |z: f32| {
+            clip(nx + side, self.position.y - 0.45, nz + z)
+                || clip(nx + side, self.position.y + 0.45, nz + z)
+        };
+        if !Self::clips(m, -1.36, blocked) {
+            self.position.x = nx;
+        } else if can_climb && !Self::clips(0.35, -2.36, blocked) {
+            self.position.x = nx;
+            climb = true;
+        } else {
+            self.velocity.x = 0.0;
+        }
+
+        let side = if self.velocity.y < 0.0 { -0.45 } else { 0.45 };
+        let blocked = |z: f32| {
+            clip(self.position.x - 0.45, ny + side, nz + z)
+                || clip(self.position.x + 0.45, ny + side, nz + z)
+        };
+        if !Self::clips(m, -1.36, blocked) {
+            self.position.y = ny;
+        } else if can_climb && !climb && !Self::clips(0.35, -2.36, blocked) {
+            self.position.y = ny;
+            climb = true;
+        } else if !climb {
+            self.velocity.y = 0.0;
+        }
+
+        if climb {
+            self.velocity.x *= 0.5;
+            self.velocity.y *= 0.5;
+            self.last_climb = self.time;
+            nz -= 1.0;
+            m = -1.35;
+        } else {
+            if self.velocity.z < 0.0 {
+                m = -m;
+            }
+            nz += self.velocity.z * dt * 32.0;
+        }
+
+        self.airborne = true;
+
+        let Position { x, y, .. } = self.position;
+        let grounded = [(-0.45, -0.45), (-0.45, 0.45), (0.45, -0.45), (0.45, 0.45)]
+            .into_iter()
+            .any(|(dx, dy)| clip(x + dx, y + dy, nz + m));
+
+        if grounded {
+            if self.velocity.z >= 0.0 {
+                self.wade = self.position.z > 61.0;
+                self.airborne = false;
+            }
+            self.velocity.z = 0.0;
+        } else {
+            self.position.z = nz - offset;
+        }
+    }
+
+    /// Tests the body of the player from `top` down to `bottom` in steps of 0.9 blocks.
+    fn clips<F>(top: f32, bottom: f32, blocked: F) -> bool
+    where
+        F: Fn(f32) -> bool,
+    {
+        let mut z = top;
+
+        while z >= bottom {
+            if blocked(z) {
+                return true;
+            }
+            z -= 0.9;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::DEFAULT_COLOR;
+
+    fn flat_world() -> World {
+        let mut world = World::new();
+
+        for x in 0..64 {
+            for y in 0..64 {
+                world.set(BlockPosition::new_xyz(x, y, 60), Some(DEFAULT_COLOR));
+            }
+        }
+
+        world
+    }
+
+    #[test]
+    fn falls_and_lands() {
+        let world = flat_world();
+        let mut player = PlayerPhysics::new(
+            Position::new_xyz(32.5, 32.5, 40.0),
+            Position::new_xyz(1.0, 0.0, 0.0),
+        );
+
+        let landing = (0..600).find_map(|_| player.step(&world, TICK));
+
+        assert!(landing.is_some());
+        assert!(!player.is_airborne());
+        // Eyes of a standing player are 3 blocks above the ground.
+        assert!((57.0..57.75).contains(&player.position.z));
+    }
+
+    /// Steps the player 60 times, returning the position after 10, 30 and 60 steps.
+    fn samples(player: &mut PlayerPhysics, world: &World) -> [Position; 3] {
+        let mut positions = [Position::default(); 3];
+        for step in 1..=60 {
+            player.step(world, TICK);
+            match step {
+                10 => positions[0] = player.position,
+                30 => positions[1] = player.position,
+                60 => positions[2] = player.position,
+                _ => {}
+            }
+        }
+
+        positions
+    }
+
+    /// Lets the player at `start` land if `land` is set, then holds `keys` and returns the
+    /// height after 10, 30 and 60 steps.
+    fn heights(world: &World, start: Position, land: bool, keys: &[InputKey]) -> [f32; 3] {
+        let mut player = PlayerPhysics::new(start, Position::new_xyz(1.0, 0.0, 0.0));
+        if land {
+            for _ in 0..180 {
+                player.step(world, TICK);
+            }
+            assert!(!player.is_airborne());
+        }
+
+        let mut input = KeyInput(0);
+        for key in keys {
+            input.set_active(*key);
+        }
+        player.set_input(world, input);
+
+        samples(&mut player, world).map(|position| position.z)
+    }
+
+    /// Lets the player land at `start`, then holds `keys` and the secondary fire with `tool`
+    /// for 60 steps, returning the x coordinate after 10, 30 and 60 steps.
+    fn trajectory(
+        world: &World,
+        start: Position,
+        keys: &[InputKey],
+        secondary: Option<ToolKind>,
+    ) -> [f32; 3] {
+        let mut player = PlayerPhysics::new(start, Position::new_xyz(1.0, 0.0, 0.0));
+        for _ in 0..180 {
+            player.step(world, TICK);
+        }
+        assert!(!player.is_airborne());
+
+        let mut input = KeyInput(0);
+        for key in keys {
+            input.set_active(*key);
+        }
+        player.set_input(world, input);
+        if let Some(tool) = secondary {
+            player.set_tool(tool);
+            player.set_weapon_input(WeaponInput(WeaponKey::Secondary.to_mask()));
+        }
+
+        samples(&mut player, world).map(|position| position.x)
+    }
+
+    fn assert_close(cases: impl IntoIterator<Item = ([f32; 3], [f32; 3])>) {
+        for (trajectory, expected) in cases {
+            for (x, expected_x) in trajectory.into_iter().zip(expected) {
+                assert!(
+                    (x - expected_x).abs() < 1e-3,
+                    "{trajectory:?} != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn movement_trajectories() {
+        // Trajectories of this implementation, not recordings of a client. They pin the
+        // movement speeds relative to each other, so changes to them are deliberate.
+        const WALK: [f32; 3] = [10.88225, 12.78851, 16.54162];
+        const SPRINT: [f32; 3] = [10.99693, 13.47507, 18.35411];
+        const CROUCH: [f32; 3] = [10.61468, 11.18655, 12.31249];
+        const SNEAK: [f32; 3] = [10.69113, 11.64426, 13.52081];
+        const WATER: [f32; 3] = [100.84271, 102.32872, 104.94736];
+
+        let world = flat_world();
+        let start = Position::new_xyz(10.5, 32.5, 57.0);
+        let up = InputKey::Up;
+        let cases = [
+            (trajectory(&world, start, &[up], None), WALK),
+            (
+                trajectory(&world, start, &[up, InputKey::Sprint], None),
+                SPRINT,
+            ),
+            (
+                trajectory(&world, start, &[up, InputKey::Crouch], None),
+                CROUCH,
+            ),
+            (
+                trajectory(&world, start, &[up, InputKey::Sneak], None),
+                SNEAK,
+            ),
+            // Only aiming down the sights of the gun slows down.
+            (trajectory(&world, start, &[up], Some(ToolKind::Gun)), SNEAK),
+            (
+                trajectory(&world, start, &[up], Some(ToolKind::Spade)),
+                WALK,
+            ),
+            // Without ground the player stands in the water at the bottom of the map.
+            (
+                trajectory(
+                    &World::new(),
+                    Position::new_xyz(100.5, 100.5, 61.0),
+                    &[up],
+                    None,
+                ),
+                WATER,
+            ),
+        ];
+
+        assert_close(cases);
+    }
+
+    #[test]
+    fn vertical_trajectories() {
+        // Like `movement_trajectories`, heights of this implementation. Holding the jump key
+        // jumps again after landing, walking into a step climbs it.
+        const JUMP: [f32; 3] = [56.44858, 56.73094, 56.15117];
+        const FALL: [f32; 3] = [40.45798, 43.48913, 51.86958];
+        const CLIMB: [f32; 3] = [57.74573, 57.74573, 56.74573];
+
+        let world = flat_world();
+        let mut steps = flat_world();
+        for x in 40..64 {
+            for y in 0..64 {
+                steps.set(BlockPosition::new_xyz(x, y, 59), Some(DEFAULT_COLOR));
+            }
+        }
+
+        let start = Position::new_xyz(36.5, 32.5, 57.0);
+        let cases = [
+            (heights(&world, start, true, &[InputKey::Jump]), JUMP),
+            (
+                heights(&world, Position::new_xyz(36.5, 32.5, 40.0), false, &[]),
+                FALL,
+            ),
+            (heights(&steps, start, true, &[InputKey::Up]), CLIMB),
+        ];
+
+        assert_close(cases);
+    }
+
+    #[test]
+    fn fall_damage() {
+        assert_eq!(Landing { velocity: 0.5 }.damage(), 0);
+        assert_eq!(Landing { velocity: 0.68 }.damage(), 40);
+        assert_eq!(Landing { velocity: 2.0 }.damage(), 255);
+
+        // Jumping off a single block does not hurt.
+        let world = flat_world();
+        let mut player = PlayerPhysics::new(
+            Position::new_xyz(32.5, 32.5, 55.0),
+            Position::new_xyz(1.0, 0.0, 0.0),
+        );
+        let landing = (0..600).find_map(|_| player.step(&world, TICK));
+        assert_eq!(landing.map(|landing| landing.damage()).unwrap_or(0), 0);
+
+        let mut player = PlayerPhysics::new(
+            Position::new_xyz(32.5, 32.5, -20.0),
+            Position::new_xyz(1.0, 0.0, 0.0),
+        );
+        let landing = (0..600).find_map(|_| player.step(&world, TICK)).unwrap();
+
+        assert!(landing.damage() > 0);
+    }
+
+    #[test]
+    fn climbs_single_block() {
+        let mut world = flat_world();
+        for x in 40..64 {
+            for y in 0..64 {
+                world.set(BlockPosition::new_xyz(x, y, 59), Some(DEFAULT_COLOR));
+            }
+        }
+
+        let mut player = PlayerPhysics::new(
+            Position::new_xyz(36.5, 32.5, 57.0),
+            Position::new_xyz(1.0, 0.0, 0.0),
+        );
+        for _ in 0..120 {
+            player.step(&world, TICK);
+        }
+
+        let mut input = KeyInput(0);
+        input.set_active(InputKey::Up);
+        player.set_input(&world, input);
+        for _ in 0..60 {
+            player.step(&world, TICK);
+        }
+
+        assert!(player.position.x > 41.0);
+        assert!(player.position.z < 57.0 && !player.is_airborne());
+    }
+}
@@ -0,0 +1,107 @@
+//! Allocation of player ids for servers.
+//!
+//! 0.75 servers have 32 slots, which is what `WorldUpdate75` covers. 0.76 and Powerthirst
+//! servers may use more, up to 255 ids as `PlayerId(255)` means "no player".
+
+use std::collections::BTreeSet;
+
+use crate::msg::{
+    model::{DisconnectReason, PlayerId},
+    msg::PlayerLeft,
+};
+
+/// Slots of a 0.75 server.
+pub const DEFAULT_CAPACITY: usize = 32;
+
+/// Number of usable player ids.
+pub const MAX_CAPACITY: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotAllocator {
+    capacity: usize,
+    used: BTreeSet<PlayerId>,
+}
+
+impl SlotAllocator {
+    /// Creates an allocator for `capacity` players, at most [`MAX_CAPACITY`].
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.min(MAX_CAPACITY),
+            used: BTreeSet::new(),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.used.len() >= self.capacity
+    }
+
+    pub fn is_allocated(&self, player_id: PlayerId) -> bool {
+        self.used.contains(&player_id)
+    }
+
+    /// The allocated ids in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.used.iter().copied()
+    }
+
+    /// Hands out the lowest free id.
+    pub fn allocate(&mut self) -> Result<PlayerId, DisconnectReason> {
+        let player_id = (0..self.capacity as u8)
+            .map(PlayerId)
+            .find(|id| !self.used.contains(id))
+            .ok_or(DisconnectReason::ServerFull)?;
+        self.used.insert(player_id);
+
+        Ok(player_id)
+    }
+
+    /// Frees the id of a player that left, returning the message announcing it.
+    pub fn release(&mut self, player_id: PlayerId) -> Option<PlayerLeft> {
+        self.used
+            .remove(&player_id)
+            .then_some(PlayerLeft { player_id })
+    }
+}
+
+impl Default for SlotAllocator {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_and_recycle() {
+        let mut slots = SlotAllocator::new(2);
+
+        assert_eq!(slots.allocate(), Ok(PlayerId(0)));
+        assert_eq!(slots.allocate(), Ok(PlayerId(1)));
+        assert_eq!(slots.allocate(), Err(DisconnectReason::ServerFull));
+
+        assert_eq!(
+            slots.release(PlayerId(0)),
+            Some(PlayerLeft {
+                player_id: PlayerId(0)
+            })
+        );
+        assert_eq!(slots.release(PlayerId(0)), None);
+        assert_eq!(slots.allocate(), Ok(PlayerId(0)));
+
+        assert_eq!(SlotAllocator::new(1000).capacity(), MAX_CAPACITY);
+    }
+}
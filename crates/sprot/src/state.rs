@@ -0,0 +1,454 @@
+//! Client side game state, kept up to date with the messages the server sends.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    gamemode::GameModeState,
+    loadout::{LoadoutChange, PendingChange},
+    msg::{
+        model::{
+            ActionKind, Color, FogColor, KeyInput, KillKind, PlayerId, PlayerPosition, Position,
+            Team, ToolKind, WeaponInput, WeaponKind,
+        },
+        msg::{
+            BlockAction, CreatePlayer, ExisitingPlayer, KillAction, Msg, StateData, WeaponReload,
+        },
+    },
+    weapon::WeaponStats,
+    world,
+};
+
+pub const MAX_HP: u8 = 100;
+pub const MAX_BLOCKS: u8 = 50;
+pub const MAX_GRENADES: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub id: PlayerId,
+    pub name: String,
+    pub team: Team,
+    pub weapon: WeaponKind,
+    pub tool: ToolKind,
+    pub color: Color,
+    pub hp: u8,
+    pub clip_ammo: u8,
+    pub reserve_ammo: u8,
+    pub blocks: u8,
+    pub grenades: u8,
+    pub kills: u32,
+    pub deaths: u32,
+    pub alive: bool,
+    pub position: PlayerPosition,
+    pub input: KeyInput,
+    pub weapon_input: WeaponInput,
+}
+
+impl Player {
+    pub fn new(id: PlayerId, name: String, team: Team, weapon: WeaponKind) -> Self {
+        let stats = WeaponStats::of(weapon);
+
+        Self {
+            id,
+            name,
+            team,
+            weapon,
+            tool: ToolKind::Gun,
+            color: Color::new_rgb(112, 112, 112),
+            hp: MAX_HP,
+            clip_ammo: stats.clip,
+            reserve_ammo: stats.reserve,
+            blocks: MAX_BLOCKS,
+            grenades: MAX_GRENADES,
+            kills: 0,
+            deaths: 0,
+            alive: true,
+            position: PlayerPosition::default(),
+            input: KeyInput(0),
+            weapon_input: WeaponInput(0),
+        }
+    }
+
+    pub const fn is_spectator(&self) -> bool {
+        self.team.0 == Team::SPECTATOR.0
+    }
+
+    /// Refills health, ammo, blocks and grenades, as done on spawn and `Restock`.
+    pub const fn restock(&mut self) {
+        let stats = WeaponStats::of(self.weapon);
+
+        self.hp = MAX_HP;
+        self.clip_ammo = stats.clip;
+        self.reserve_ammo = stats.reserve;
+        self.blocks = MAX_BLOCKS;
+        self.grenades = MAX_GRENADES;
+    }
+
+    /// Restocks and revives the player at `position`, as done on `CreatePlayer`.
+    pub const fn spawn(&mut self, position: Position) {
+        self.restock();
+        self.alive = true;
+        self.tool = ToolKind::Gun;
+        self.position.position = position;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamInfo {
+    pub name: String,
+    pub color: Color,
+}
+
+/// The state of a match as seen by a client.
+///
+/// Feed every message received from the server into [`GameState::update`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameState {
+    local_player: Option<PlayerId>,
+    players: BTreeMap<PlayerId, Player>,
+    teams: Option<[TeamInfo; 2]>,
+    fog_color: Option<FogColor>,
+    gamemode: Option<GameModeState>,
+}
+
+impl GameState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The id assigned to this client by `StateData`.
+    pub const fn local_player_id(&self) -> Option<PlayerId> {
+        self.local_player
+    }
+
+    pub fn local_player(&self) -> Option<&Player> {
+        self.local_player.and_then(|id| self.players.get(&id))
+    }
+
+    pub fn player(&self, id: PlayerId) -> Option<&Player> {
+        self.players.get(&id)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.players.values()
+    }
+
+    pub fn team_players(&self, team: Team) -> impl Iterator<Item = &Player> {
+        self.players().filter(move |player| player.team == team)
+    }
+
+    /// Starts a team or weapon change of the local player. `None` before `StateData` or if
+    /// the player already has the team or weapon.
+    pub fn request_change(&self, change: LoadoutChange) -> Option<PendingChange> {
+        let player_id = self.local_player?;
+        let unchanged = self.player(player_id).is_some_and(|player| match change {
+            LoadoutChange::Team(team) => player.team == team,
+            LoadoutChange::Weapon(weapon) => player.weapon == weapon,
+        });
+
+        (!unchanged).then_some(PendingChange::new(player_id, change))
+    }
+
+    pub const fn team(&self, team: Team) -> Option<&TeamInfo> {
+        match (&self.teams, team.index()) {
+            (Some(teams), Some(index)) => Some(&teams[index]),
+            _ => None,
+        }
+    }
+
+    pub const fn fog_color(&self) -> Option<FogColor> {
+        self.fog_color
+    }
+
+    pub const fn gamemode(&self) -> Option<&GameModeState> {
+        self.gamemode.as_ref()
+    }
+
+    pub fn update(&mut self, msg: &Msg) {
+        match msg {
+            Msg::StateData(state) => self.state_data(state),
+            Msg::MapStart75(_) | Msg::MapStart76(_) => {
+                // The server resends all players and the state after the map transfer.
+                self.players.clear();
+                self.gamemode = None;
+            }
+            Msg::ExisitingPlayer(existing) => self.existing_player(existing),
+            Msg::CreatePlayer(create) => self.create_player(create),
+            Msg::ShortPlayerData(short) => {
+                if let Some(player) = self.players.get_mut(&short.player_id) {
+                    player.team = short.team;
+                    player.weapon = short.weapon;
+                }
+            }
+            Msg::PlayerLeft(left) => {
+                self.players.remove(&left.player_id);
+            }
+            Msg::KillAction(kill) => self.kill_action(kill),
+            Msg::SetHP(set_hp) => {
+                if let Some(player) = self.local_player_mut() {
+                    player.hp = set_hp.hp;
+                }
+            }
+            Msg::PositionData(position) => {
+                if let Some(player) = self.local_player_mut() {
+                    player.position.position = position.position;
+                }
+            }
+            Msg::OrientationData(orientation) => {
+                if let Some(player) = self.local_player_mut() {
+                    player.position.orientation = orientation.position;
+                }
+            }
+            Msg::WorldUpdate75(update) => {
+                for (id, position) in update.player_positions.iter().enumerate() {
+                    self.set_player_position(PlayerId(id as u8), position);
+                }
+            }
+            Msg::WorldUpdate76(update) => {
+                for (id, position) in &update.player_positions {
+                    self.set_player_position(*id, position);
+                }
+            }
+            Msg::InputData(input) => {
+                if let Some(player) = self.players.get_mut(&input.player_id) {
+                    player.input = input.state;
+                }
+            }
+            Msg::WeaponInput(input) => {
+                if let Some(player) = self.players.get_mut(&input.player_id) {
+                    player.weapon_input = input.state;
+                }
+            }
+            Msg::WeaponReload(reload) => self.weapon_reload(reload),
+            Msg::Restock(restock) => {
+                if let Some(player) = self.players.get_mut(&restock.player_id) {
+                    player.restock();
+                }
+            }
+            Msg::SetColor(set_color) => {
+                if let Some(player) = self.players.get_mut(&set_color.player_id) {
+                    player.color = set_color.color;
+                }
+            }
+            Msg::SetTool(set_tool) => {
+                if let Some(player) = self.players.get_mut(&set_tool.player_id) {
+                    player.tool = set_tool.kind;
+                }
+            }
+            Msg::ChangeWeapon(change) => {
+                if let Some(player) = self.players.get_mut(&change.player_id) {
+                    player.weapon = change.kind;
+                }
+            }
+            Msg::GrenadePacket(grenade) => {
+                if let Some(player) = self.players.get_mut(&grenade.player_id) {
+                    player.grenades = player.grenades.saturating_sub(1);
+                }
+            }
+            Msg::BlockAction(action) => self.block_action(action),
+            Msg::BlockLine(line) => {
+                if let Some(player) = self.players.get_mut(&line.player_id) {
+                    let cost = world::block_line(line.start, line.end).len();
+                    player.blocks = player.blocks.saturating_sub(cost as u8);
+                }
+            }
+            Msg::FogColor(fog) => self.fog_color = Some(fog.color),
+            Msg::IntelPickup(_)
+            | Msg::IntelDrop(_)
+            | Msg::IntelCapture(_)
+            | Msg::MoveObject(_)
+            | Msg::TerritoryCapture(_)
+            | Msg::ProgressBar(_) => {
+                let players = &self.players;
+
+                if let Some(gamemode) = &mut self.gamemode {
+                    gamemode.update(msg, |id| players.get(&id).map(|player| player.team));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn local_player_mut(&mut self) -> Option<&mut Player> {
+        self.local_player.and_then(|id| self.players.get_mut(&id))
+    }
+
+    /// Servers send the origin for dead players, a player from the join burst is alive once it
+    /// shows up somewhere else.
+    fn set_player_position(&mut self, id: PlayerId, position: &PlayerPosition) {
+        if let Some(player) = self.players.get_mut(&id) {
+            player.position = *position;
+            if position.position != Position::default() && player.hp > 0 {
+                player.alive = true;
+            }
+        }
+    }
+
+    fn state_data(&mut self, state: &StateData) {
+        self.local_player = Some(state.player_id);
+        self.fog_color = Some(FogColor::from_color(state.fog_color, 0));
+        self.teams = Some([
+            TeamInfo {
+                name: state.team1_name.clone(),
+                color: state.team1_color,
+            },
+            TeamInfo {
+                name: state.team2_name.clone(),
+                color: state.team2_color,
+            },
+        ]);
+        self.gamemode = GameModeState::from_state_data(state);
+    }
+
+    /// The join burst does not tell whether players are alive, so new players are dead until a
+    /// `CreatePlayer` or `WorldUpdate` places them.
+    fn existing_player(&mut self, existing: &ExisitingPlayer) {
+        let player = self.players.entry(existing.player_id).or_insert_with(|| {
+            let mut player = Player::new(
+                existing.player_id,
+                existing.name.clone(),
+                existing.team,
+                existing.weapon,
+            );
+            player.alive = false;
+            player
+        });
+
+        player.name.clone_from(&existing.name);
+        player.team = existing.team;
+        player.weapon = existing.weapon;
+        player.tool = existing.held_item;
+        player.kills = existing.kills;
+        player.color = existing.color;
+    }
+
+    fn create_player(&mut self, create: &CreatePlayer) {
+        let player = self.players.entry(create.player_id).or_insert_with(|| {
+            Player::new(
+                create.player_id,
+                create.name.clone(),
+                create.team,
+                create.weapon,
+            )
+        });
+
+        player.name.clone_from(&create.name);
+        player.team = create.team;
+        player.weapon = create.weapon;
+        player.spawn(create.position);
+    }
+
+    fn kill_action(&mut self, kill: &KillAction) {
+        if let Some(victim) = self.players.get_mut(&kill.player_id) {
+            victim.hp = 0;
+            victim.alive = false;
+            victim.deaths += 1;
+        }
+
+        let counts_as_kill = !matches!(kill.kind, KillKind::TeamChange | KillKind::ClassChange)
+            && kill.killer_id != kill.player_id;
+
+        if counts_as_kill {
+            if let Some(killer) = self.players.get_mut(&kill.killer_id) {
+                killer.kills += 1;
+            }
+        }
+    }
+
+    fn weapon_reload(&mut self, reload: &WeaponReload) {
+        if let Some(player) = self.players.get_mut(&reload.player_id) {
+            player.clip_ammo = reload.clip_ammo;
+            player.reserve_ammo = reload.reserve_ammo;
+        }
+    }
+
+    fn block_action(&mut self, action: &BlockAction) {
+        if let Some(player) = self.players.get_mut(&action.player_id) {
+            match action.kind {
+                ActionKind::Build => player.blocks = player.blocks.saturating_sub(1),
+                // Digging with the spade gives a block back.
+                ActionKind::BSLDestroy if player.tool == ToolKind::Spade => {
+                    player.blocks = (player.blocks + 1).min(MAX_BLOCKS)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::msg::{PlayerLeft, SetTool, WorldUpdate76};
+
+    #[test]
+    fn player_lifecycle() {
+        let mut state = GameState::new();
+
+        state.update(&Msg::CreatePlayer(CreatePlayer {
+            player_id: PlayerId(3),
+            weapon: WeaponKind::Smg,
+            team: Team::GREEN,
+            position: Position::new_xyz(256.0, 256.0, 30.0),
+            name: String::from("Deuce"),
+        }));
+        state.update(&Msg::SetTool(SetTool {
+            player_id: PlayerId(3),
+            kind: ToolKind::Block,
+        }));
+
+        let player = state.player(PlayerId(3)).unwrap();
+        assert_eq!(player.tool, ToolKind::Block);
+        assert_eq!(player.clip_ammo, 30);
+        assert!(player.alive);
+
+        state.update(&Msg::KillAction(KillAction {
+            player_id: PlayerId(3),
+            killer_id: PlayerId(3),
+            kind: KillKind::Fall,
+            respawn_time: 5,
+        }));
+
+        let player = state.player(PlayerId(3)).unwrap();
+        assert!(!player.alive);
+        assert_eq!((player.kills, player.deaths), (0, 1));
+
+        state.update(&Msg::PlayerLeft(PlayerLeft {
+            player_id: PlayerId(3),
+        }));
+        assert_eq!(state.players().count(), 0);
+    }
+
+    #[test]
+    fn existing_players_are_dead_until_placed() {
+        let mut state = GameState::new();
+        let existing = |player_id| {
+            Msg::ExisitingPlayer(ExisitingPlayer {
+                player_id,
+                team: Team::BLUE,
+                weapon: WeaponKind::Rifle,
+                held_item: ToolKind::Gun,
+                kills: 2,
+                color: Color::new_rgb(1, 2, 3),
+                name: String::from("Deuce"),
+            })
+        };
+        state.update(&existing(PlayerId(1)));
+        state.update(&existing(PlayerId(2)));
+        assert!(state.players().all(|player| !player.alive));
+
+        let placed = PlayerPosition {
+            position: Position::new_xyz(10.0, 20.0, 30.0),
+            orientation: Position::new_xyz(1.0, 0.0, 0.0),
+        };
+        let dead = PlayerPosition {
+            position: Position::default(),
+            orientation: Position::default(),
+        };
+        state.update(&Msg::WorldUpdate76(WorldUpdate76 {
+            player_positions: vec![(PlayerId(1), placed), (PlayerId(2), dead)],
+        }));
+
+        assert!(state.player(PlayerId(1)).unwrap().alive);
+        assert!(!state.player(PlayerId(2)).unwrap().alive);
+    }
+}
@@ -0,0 +1,224 @@
+//! Weapon balance and damage, using the numbers of the original 0.75 server.
+
+use crate::{
+    msg::{
+        model::{DamageKind, HitKind, KillKind, PlayerId, Position, WeaponKind},
+        msg::{KillAction, SetHP},
+    },
+    state::MAX_HP,
+};
+
+/// Damage of a spade hit.
+pub const MELEE_DAMAGE: u8 = 80;
+
+/// Seconds a killed player waits before respawning.
+pub const RESPAWN_TIME: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeaponStats {
+    pub clip: u8,
+    pub reserve: u8,
+    /// Seconds between two shots.
+    pub delay: f32,
+    /// Seconds a reload takes, for a single shell if `reload_per_shell` is set.
+    pub reload_time: f32,
+    pub reload_per_shell: bool,
+    /// Random deviation of the shot direction.
+    pub spread: f32,
+    /// Number of bullets per shot.
+    pub pellets: u8,
+    /// Damage per hit location, indexed like `HitKind` (torso, head, arms, legs).
+    damage: [u8; 4],
+}
+
+pub const RIFLE: WeaponStats = WeaponStats {
+    clip: 10,
+    reserve: 50,
+    delay: 0.5,
+    reload_time: 2.5,
+    reload_per_shell: false,
+    spread: 0.006,
+    pellets: 1,
+    damage: [49, 100, 33, 33],
+};
+
+pub const SMG: WeaponStats = WeaponStats {
+    clip: 30,
+    reserve: 120,
+    delay: 0.11,
+    reload_time: 2.5,
+    reload_per_shell: false,
+    spread: 0.012,
+    pellets: 1,
+    damage: [29, 75, 18, 18],
+};
+
+pub const SHOTGUN: WeaponStats = WeaponStats {
+    clip: 6,
+    reserve: 48,
+    delay: 1.0,
+    reload_time: 0.5,
+    reload_per_shell: true,
+    spread: 0.024,
+    pellets: 8,
+    damage: [27, 37, 16, 16],
+};
+
+impl WeaponStats {
+    pub const fn of(weapon: WeaponKind) -> &'static Self {
+        match weapon {
+            WeaponKind::Rifle => &RIFLE,
+            WeaponKind::Smg => &SMG,
+            WeaponKind::Shotgun => &SHOTGUN,
+        }
+    }
+
+    /// Damage of a single bullet hitting `kind`, melee hits use the spade.
+    pub const fn damage(&self, kind: HitKind) -> u8 {
+        match kind {
+            HitKind::Torso => self.damage[0],
+            HitKind::Head => self.damage[1],
+            HitKind::Arms => self.damage[2],
+            HitKind::Legs => self.damage[3],
+            HitKind::Melee => MELEE_DAMAGE,
+        }
+    }
+
+    /// Seconds it takes to refill the clip from `clip_ammo`.
+    pub fn full_reload_time(&self, clip_ammo: u8) -> f32 {
+        if self.reload_per_shell {
+            f32::from(self.clip.saturating_sub(clip_ammo)) * self.reload_time
+        } else {
+            self.reload_time
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Damage {
+    pub victim: PlayerId,
+    pub killer: PlayerId,
+    pub amount: u8,
+    pub damage_kind: DamageKind,
+    /// Kind of the kill, if the damage is lethal.
+    pub kill_kind: KillKind,
+    pub source_position: Position,
+}
+
+impl Damage {
+    /// A hit reported by `shooter` with a `HitPacket`.
+    pub const fn hit(
+        shooter: PlayerId,
+        shooter_position: Position,
+        weapon: WeaponKind,
+        victim: PlayerId,
+        kind: HitKind,
+    ) -> Self {
+        let kill_kind = match kind {
+            HitKind::Head => KillKind::Headshot,
+            HitKind::Melee => KillKind::Melee,
+            HitKind::Torso | HitKind::Arms | HitKind::Legs => KillKind::Weapon,
+        };
+
+        Self {
+            victim,
+            killer: shooter,
+            amount: WeaponStats::of(weapon).damage(kind),
+            damage_kind: DamageKind::Weapon,
+            kill_kind,
+            source_position: shooter_position,
+        }
+    }
+
+    /// Damage of a grenade thrown by `thrower`, see `Explosion::damage`.
+    pub fn grenade(thrower: PlayerId, position: Position, victim: PlayerId, amount: f32) -> Self {
+        Self {
+            victim,
+            killer: thrower,
+            amount: amount.clamp(0.0, f32::from(MAX_HP)) as u8,
+            damage_kind: DamageKind::Weapon,
+            kill_kind: KillKind::Grenade,
+            source_position: position,
+        }
+    }
+
+    /// Damage of a hard landing, see `Landing::damage`. Players are their own killer.
+    pub const fn fall(player: PlayerId, position: Position, amount: u8) -> Self {
+        Self {
+            victim: player,
+            killer: player,
+            amount,
+            damage_kind: DamageKind::Fall,
+            kill_kind: KillKind::Fall,
+            source_position: position,
+        }
+    }
+
+    /// Applies the damage to a player with `hp` health.
+    pub const fn apply(&self, hp: u8, respawn_time: u8) -> DamageOutcome {
+        match hp.checked_sub(self.amount) {
+            Some(hp) if hp > 0 => DamageOutcome::Damaged(SetHP {
+                hp,
+                damage_kind: self.damage_kind,
+                source_position: self.source_position,
+            }),
+            _ => DamageOutcome::Killed(KillAction {
+                player_id: self.victim,
+                killer_id: self.killer,
+                kind: self.kill_kind,
+                respawn_time,
+            }),
+        }
+    }
+}
+
+/// The message the server sends after a player took damage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DamageOutcome {
+    /// Sent to the damaged player only.
+    Damaged(SetHP),
+    /// Sent to all players.
+    Killed(KillAction),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rifle_headshot_kills() {
+        let shooter = PlayerId(1);
+        let victim = PlayerId(2);
+        let position = Position::new_xyz(1.0, 2.0, 3.0);
+
+        let torso = Damage::hit(shooter, position, WeaponKind::Rifle, victim, HitKind::Torso);
+        assert_eq!(
+            torso.apply(MAX_HP, RESPAWN_TIME),
+            DamageOutcome::Damaged(SetHP {
+                hp: 51,
+                damage_kind: DamageKind::Weapon,
+                source_position: position,
+            })
+        );
+        assert!(matches!(
+            torso.apply(49, RESPAWN_TIME),
+            DamageOutcome::Killed(KillAction {
+                kind: KillKind::Weapon,
+                ..
+            })
+        ));
+
+        let head = Damage::hit(shooter, position, WeaponKind::Rifle, victim, HitKind::Head);
+        assert_eq!(
+            head.apply(MAX_HP, RESPAWN_TIME),
+            DamageOutcome::Killed(KillAction {
+                player_id: victim,
+                killer_id: shooter,
+                kind: KillKind::Headshot,
+                respawn_time: RESPAWN_TIME,
+            })
+        );
+
+        assert_eq!(SHOTGUN.full_reload_time(4), 1.0);
+    }
+}
@@ -0,0 +1,898 @@
+//! The voxel world of a map.
+//!
+//! Format: <http://silverspaceship.com/aosmap/aos_file_format.html>
+
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::{
+    error::MapError,
+    msg::{
+        model::{ActionKind, BlockPosition, Color, PlayerId, Position},
+        msg::{BlockAction, BlockLine, Msg},
+    },
+};
+
+pub const MAP_X: i32 = 512;
+pub const MAP_Y: i32 = 512;
+pub const MAP_Z: i32 = 64;
+
+/// The two lowest layers (water) can neither be built on nor destroyed.
+pub const MAX_EDIT_Z: i32 = MAP_Z - 2;
+
+/// Maximum number of blocks placed by a single `BlockLine`.
+pub const MAX_BLOCK_LINE_LENGTH: usize = 50;
+
+/// Color of solid voxels that are not stored in the map, as they are not visible.
+pub const DEFAULT_COLOR: Color = Color::new_rgb(103, 64, 40);
+
+const COLUMN_SIZE: usize = MAP_Z as usize;
+const VOXEL_COUNT: usize = (MAP_X * MAP_Y * MAP_Z) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoxelChange {
+    Built(BlockPosition, Color),
+    Destroyed(BlockPosition),
+}
+
+impl VoxelChange {
+    pub const fn position(&self) -> BlockPosition {
+        match self {
+            Self::Built(position, _) | Self::Destroyed(position) => *position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub block: BlockPosition,
+    /// Distance from the origin of the ray.
+    pub distance: f32,
+    /// The point where the ray entered the block.
+    pub position: Position,
+}
+
+/// A component of blocks that is no longer connected to the ground.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallingChunk {
+    pub blocks: Vec<(BlockPosition, Color)>,
+}
+
+impl FallingChunk {
+    pub const fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The minimum and maximum corner of the chunk.
+    pub fn bounds(&self) -> (BlockPosition, BlockPosition) {
+        let mut min = BlockPosition::new_xyz(i32::MAX, i32::MAX, i32::MAX);
+        let mut max = BlockPosition::new_xyz(i32::MIN, i32::MIN, i32::MIN);
+
+        for (position, _) in &self.blocks {
+            min = BlockPosition::new_xyz(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            );
+            max = BlockPosition::new_xyz(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            );
+        }
+
+        (min, max)
+    }
+}
+
+/// The result of removing floating blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Collapse {
+    pub removals: Vec<VoxelChange>,
+    pub chunks: Vec<FallingChunk>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct World {
+    solid: Vec<u64>,
+    colors: HashMap<usize, Color>,
+}
+
+impl World {
+    /// An empty world, which only consists of air.
+    pub fn new() -> Self {
+        Self {
+            solid: vec![0; VOXEL_COUNT / 64],
+            colors: HashMap::new(),
+        }
+    }
+
+    /// Loads the world from uncompressed VXL data.
+    pub fn from_vxl(data: &[u8]) -> Result<Self, MapError> {
+        let mut world = Self::new();
+        world.solid.fill(u64::MAX);
+
+        let mut offset = 0;
+        let byte = |offset: usize| {
+            data.get(offset)
+                .copied()
+                .ok_or(MapError::UnexpectedEnd { offset })
+        };
+        let color = |offset: usize| match data.get(offset..offset + 4) {
+            Some(&[b, g, r, _]) => Ok(Color::new_rgb(r, g, b)),
+            _ => Err(MapError::UnexpectedEnd { offset }),
+        };
+
+        for y in 0..MAP_Y {
+            for x in 0..MAP_X {
+                let mut z = 0;
+
+                loop {
+                    let chunks = byte(offset)? as i32;
+                    let top_start = byte(offset + 1)? as i32;
+                    let top_end = byte(offset + 2)? as i32;
+
+                    if top_start < z
+                        || top_start >= MAP_Z
+                        || top_end >= MAP_Z
+                        || top_end + 1 < top_start
+                    {
+                        return Err(MapError::InvalidSpan { x, y });
+                    }
+
+                    for air in z..top_start {
+                        world.set_raw(BlockPosition::new_xyz(x, y, air), None);
+                    }
+
+                    let mut color_offset = offset + 4;
+                    for top in top_start..=top_end {
+                        let c = color(color_offset)?;
+                        world.set_raw(BlockPosition::new_xyz(x, y, top), Some(c));
+                        color_offset += 4;
+                    }
+
+                    let top_len = top_end - top_start + 1;
+                    if chunks == 0 {
+                        offset += 4 * (top_len as usize + 1);
+                        break;
+                    }
+
+                    let bottom_len = (chunks - 1) - top_len;
+                    if bottom_len < 0 {
+                        return Err(MapError::InvalidSpan { x, y });
+                    }
+                    offset += chunks as usize * 4;
+
+                    // The air start of the next span is the end of the bottom colors.
+                    let bottom_end = byte(offset + 3)? as i32;
+                    let bottom_start = bottom_end - bottom_len;
+                    if bottom_start <= top_end || bottom_end > MAP_Z {
+                        return Err(MapError::InvalidSpan { x, y });
+                    }
+
+                    for bottom in bottom_start..bottom_end {
+                        let c = color(color_offset)?;
+                        world.set_raw(BlockPosition::new_xyz(x, y, bottom), Some(c));
+                        color_offset += 4;
+                    }
+
+                    z = bottom_end;
+                }
+            }
+        }
+
+        if offset != data.len() {
+            return Err(MapError::TrailingData { offset });
+        }
+
+        Ok(world)
+    }
+
+    /// Saves the world as uncompressed VXL data, the inverse of [`World::from_vxl`].
+    ///
+    /// Only the colors of visible voxels are stored, as the format requires.
+    pub fn to_vxl(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for y in 0..MAP_Y {
+            self.row_to_vxl(y, &mut data);
+        }
+
+        data
+    }
+
+    /// Appends the VXL data of the columns at `y`, so that the rows in order make up
+    /// [`World::to_vxl`].
+    pub fn row_to_vxl(&self, y: i32, data: &mut Vec<u8>) {
+        for x in 0..MAP_X {
+            let solid = |z: i32| self.is_solid(BlockPosition::new_xyz(x, y, z));
+            let surface = |z: i32| self.is_surface(BlockPosition::new_xyz(x, y, z));
+            let mut z = 0;
+
+            while z < MAP_Z {
+                let air_start = z;
+                while z < MAP_Z && !solid(z) {
+                    z += 1;
+                }
+
+                // Columns always end with a solid voxel.
+                let top_start = z.min(MAP_Z - 1);
+                while z < MAP_Z && surface(z) {
+                    z += 1;
+                }
+                let top_end = z.max(top_start + 1);
+
+                while z < MAP_Z && solid(z) && !surface(z) {
+                    z += 1;
+                }
+
+                // Colored voxels that reach the bottom are the top colors of the next span.
+                let bottom_start = z;
+                let mut bottom_end = z;
+                while bottom_end < MAP_Z && surface(bottom_end) {
+                    bottom_end += 1;
+                }
+                if bottom_end < MAP_Z {
+                    z = bottom_end;
+                } else {
+                    bottom_end = bottom_start;
+                }
+
+                let colors = (top_end - top_start) + (bottom_end - bottom_start);
+                let chunks = if z >= MAP_Z { 0 } else { colors + 1 };
+                data.extend([chunks as u8, top_start as u8, (top_end - 1) as u8]);
+                data.push(air_start as u8);
+
+                for color in (top_start..top_end).chain(bottom_start..bottom_end) {
+                    let [r, g, b] = self
+                        .color(BlockPosition::new_xyz(x, y, color))
+                        .unwrap_or(DEFAULT_COLOR)
+                        .to_rgb();
+                    data.extend([b, g, r, 0x7f]);
+                }
+
+                z = z.max(top_end);
+            }
+        }
+    }
+
+    pub const fn in_bounds(position: BlockPosition) -> bool {
+        position.x >= 0
+            && position.x < MAP_X
+            && position.y >= 0
+            && position.y < MAP_Y
+            && position.z >= 0
+            && position.z < MAP_Z
+    }
+
+    /// Whether a block can be built at or destroyed from `position`.
+    pub const fn is_editable(position: BlockPosition) -> bool {
+        Self::in_bounds(position) && position.z < MAX_EDIT_Z
+    }
+
+    /// Everything below the map is solid, everything else outside of the map is air.
+    pub fn is_solid(&self, position: BlockPosition) -> bool {
+        if position.z >= MAP_Z {
+            return true;
+        }
+
+        match Self::index(position) {
+            Some(index) => self.solid[index / 64] & (1 << (index % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// The height of the highest solid voxel of a column, the bottom layer if the column is empty.
+    pub fn ground_level(&self, x: i32, y: i32) -> i32 {
+        (0..MAP_Z)
+            .find(|z| self.is_solid(BlockPosition::new_xyz(x, y, *z)))
+            .unwrap_or(MAP_Z - 1)
+    }
+
+    /// The color of a solid voxel.
+    pub fn color(&self, position: BlockPosition) -> Option<Color> {
+        let index = Self::index(position)?;
+
+        if !self.is_solid(position) {
+            return None;
+        }
+
+        Some(self.colors.get(&index).copied().unwrap_or(DEFAULT_COLOR))
+    }
+
+    /// Sets a voxel to solid with the given color or to air, returning the previous color.
+    pub fn set(&mut self, position: BlockPosition, color: Option<Color>) -> Option<Color> {
+        let previous = self.color(position);
+        self.set_raw(position, color);
+
+        previous
+    }
+
+    /// Places a block, returning the change if there was no block before.
+    pub fn build(&mut self, position: BlockPosition, color: Color) -> Option<VoxelChange> {
+        if !Self::is_editable(position) || self.is_solid(position) {
+            return None;
+        }
+
+        self.set_raw(position, Some(color));
+
+        Some(VoxelChange::Built(position, color))
+    }
+
+    /// Removes a block, returning the change if there was a block before.
+    pub fn destroy(&mut self, position: BlockPosition) -> Option<VoxelChange> {
+        if !Self::is_editable(position) || !self.is_solid(position) {
+            return None;
+        }
+
+        self.set_raw(position, None);
+
+        Some(VoxelChange::Destroyed(position))
+    }
+
+    /// Applies a `BlockAction`, `color` is the color selected by the player.
+    pub fn apply_block_action(&mut self, action: &BlockAction, color: Color) -> Vec<VoxelChange> {
+        let position = action.position;
+
+        match action.kind {
+            ActionKind::Build => self.build(position, color).into_iter().collect(),
+            ActionKind::BSLDestroy => self.destroy(position).into_iter().collect(),
+            ActionKind::SRDestroy => (-1..=1)
+                .filter_map(|z| self.destroy(position.offset(0, 0, z)))
+                .collect(),
+            ActionKind::GDestroy => {
+                let mut changes = Vec::new();
+
+                for x in -1..=1 {
+                    for y in -1..=1 {
+                        for z in -1..=1 {
+                            changes.extend(self.destroy(position.offset(x, y, z)));
+                        }
+                    }
+                }
+
+                changes
+            }
+        }
+    }
+
+    /// Applies a `BlockLine`, `color` is the color selected by the player.
+    pub fn apply_block_line(&mut self, line: &BlockLine, color: Color) -> Vec<VoxelChange> {
+        block_line(line.start, line.end)
+            .into_iter()
+            .filter_map(|position| self.build(position, color))
+            .collect()
+    }
+
+    /// Applies block messages, using `color_of` to look up the color selected by a player.
+    ///
+    /// Blocks that are no longer connected to the ground after a destroy are removed as well, as
+    /// the clients do the same.
+    pub fn update<F>(&mut self, msg: &Msg, color_of: F) -> Vec<VoxelChange>
+    where
+        F: Fn(PlayerId) -> Option<Color>,
+    {
+        match msg {
+            Msg::BlockAction(action) => {
+                let color = color_of(action.player_id).unwrap_or(DEFAULT_COLOR);
+                let mut changes = self.apply_block_action(action, color);
+                let collapse = self.collapse(&changes);
+
+                changes.extend(collapse.removals);
+                changes
+            }
+            Msg::BlockLine(line) => {
+                let color = color_of(line.player_id).unwrap_or(DEFAULT_COLOR);
+                self.apply_block_line(line, color)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Casts a ray through the voxels, returning the first solid block within `max_distance`.
+    pub fn raycast(
+        &self,
+        origin: Position,
+        direction: Position,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let length =
+            (direction.x * direction.x + direction.y * direction.y + direction.z * direction.z)
+                .sqrt();
+        if length == 0.0 {
+            return None;
+        }
+
+        let origin = [origin.x, origin.y, origin.z];
+        let direction = [
+            direction.x / length,
+            direction.y / length,
+            direction.z / length,
+        ];
+
+        let mut block = origin.map(|v| v.floor() as i32);
+        let mut step = [0; 3];
+        let mut next = [f32::INFINITY; 3];
+        let mut delta = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                delta[axis] = 1.0 / direction[axis];
+                next[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                delta[axis] = -1.0 / direction[axis];
+                next[axis] = (origin[axis] - block[axis] as f32) * delta[axis];
+            }
+        }
+
+        let mut distance = 0.0;
+
+        loop {
+            let position = BlockPosition::new_xyz(block[0], block[1], block[2]);
+            if self.is_solid(position) {
+                return Some(RayHit {
+                    block: position,
+                    distance,
+                    position: Position::new_xyz(
+                        origin[0] + direction[0] * distance,
+                        origin[1] + direction[1] * distance,
+                        origin[2] + direction[2] * distance,
+                    ),
+                });
+            }
+
+            let axis = if next[0] <= next[1] && next[0] <= next[2] {
+                0
+            } else if next[1] <= next[2] {
+                1
+            } else {
+                2
+            };
+
+            distance = next[axis];
+            if distance > max_distance {
+                return None;
+            }
+
+            block[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+    }
+
+    /// Whether there is no solid block between the two points.
+    pub fn can_see(&self, from: Position, to: Position) -> bool {
+        let direction = Position::new_xyz(to.x - from.x, to.y - from.y, to.z - from.z);
+        let distance =
+            (direction.x * direction.x + direction.y * direction.y + direction.z * direction.z)
+                .sqrt();
+
+        self.raycast(from, direction, distance).is_none()
+    }
+
+    /// Whether the block at `position` is connected to the indestructible bottom layers.
+    pub fn is_grounded(&self, position: BlockPosition) -> bool {
+        self.component(position, &mut HashSet::new()).is_none()
+    }
+
+    /// Finds all blocks next to the destroyed blocks in `changes` that are no longer connected
+    /// to the ground.
+    pub fn floating_chunks(&self, changes: &[VoxelChange]) -> Vec<FallingChunk> {
+        let mut visited = HashSet::new();
+        let mut chunks = Vec::new();
+
+        for change in changes {
+            let VoxelChange::Destroyed(position) = change else {
+                continue;
+            };
+
+            for neighbour in neighbours(*position) {
+                if visited.contains(&neighbour) || !self.is_solid(neighbour) {
+                    continue;
+                }
+
+                if let Some(blocks) = self.component(neighbour, &mut visited) {
+                    let blocks = blocks
+                        .into_iter()
+                        .map(|block| (block, self.color(block).unwrap_or(DEFAULT_COLOR)))
+                        .collect();
+
+                    chunks.push(FallingChunk { blocks });
+                }
+            }
+        }
+
+        chunks
+    }
+
+    /// Removes all blocks that are floating after the destroyed blocks in `changes` were removed.
+    pub fn collapse(&mut self, changes: &[VoxelChange]) -> Collapse {
+        let chunks = self.floating_chunks(changes);
+        let removals = chunks
+            .iter()
+            .flat_map(|chunk| chunk.blocks.iter())
+            .map(|&(position, _)| {
+                self.set_raw(position, None);
+                VoxelChange::Destroyed(position)
+            })
+            .collect();
+
+        Collapse { removals, chunks }
+    }
+
+    /// Flood fills the solid component containing `start`.
+    ///
+    /// Returns `None` as soon as the ground is reached, otherwise the blocks of the component.
+    /// Blocks further down are visited first, as most components are connected to the ground.
+    fn component(
+        &self,
+        start: BlockPosition,
+        visited: &mut HashSet<BlockPosition>,
+    ) -> Option<Vec<BlockPosition>> {
+        let mut blocks = Vec::new();
+        let mut queue = BinaryHeap::from([(start.z, start)]);
+        visited.insert(start);
+
+        while let Some((_, position)) = queue.pop() {
+            if position.z >= MAX_EDIT_Z {
+                return None;
+            }
+
+            blocks.push(position);
+
+            for neighbour in neighbours(position) {
+                if self.is_solid(neighbour) && visited.insert(neighbour) {
+                    queue.push((neighbour.z, neighbour));
+                }
+            }
+        }
+
+        Some(blocks)
+    }
+
+    /// Whether a solid voxel is next to air. The top layer is always visible, the sides of the
+    /// map are not.
+    fn is_surface(&self, position: BlockPosition) -> bool {
+        if !self.is_solid(position) {
+            return false;
+        }
+
+        position.z == 0
+            || neighbours(position)
+                .into_iter()
+                .any(|neighbour| Self::in_bounds(neighbour) && !self.is_solid(neighbour))
+    }
+
+    const fn index(position: BlockPosition) -> Option<usize> {
+        if !Self::in_bounds(position) {
+            return None;
+        }
+
+        let column = (position.y * MAP_X + position.x) as usize;
+
+        Some(column * COLUMN_SIZE + position.z as usize)
+    }
+
+    fn set_raw(&mut self, position: BlockPosition, color: Option<Color>) {
+        let Some(index) = Self::index(position) else {
+            return;
+        };
+
+        match color {
+            Some(color) => {
+                self.solid[index / 64] |= 1 << (index % 64);
+                self.colors.insert(index, color);
+            }
+            None => {
+                self.solid[index / 64] &= !(1 << (index % 64));
+                self.colors.remove(&index);
+            }
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for World {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("World")
+            .field("colors", &self.colors.len())
+            .finish_non_exhaustive()
+    }
+}
+
+const fn neighbours(position: BlockPosition) -> [BlockPosition; 6] {
+    [
+        position.offset(-1, 0, 0),
+        position.offset(1, 0, 0),
+        position.offset(0, -1, 0),
+        position.offset(0, 1, 0),
+        position.offset(0, 0, -1),
+        position.offset(0, 0, 1),
+    ]
+}
+
+/// Blocks of a `BlockLine`, in the order they are placed from `start` to `end`.
+///
+/// This is the `cubeline` algorithm of voxlap, as also used by OpenSpades:
+/// <https://github.com/yvt/openspades/blob/master/Sources/Client/World.cpp>
+///
+/// Every step moves along a single axis. The line is cut at [`MAX_BLOCK_LINE_LENGTH`] blocks and
+/// at the map bounds.
+pub fn block_line(start: BlockPosition, end: BlockPosition) -> Vec<BlockPosition> {
+    // Step length of an axis the line does not move along.
+    const NO_STEP: i64 = 0x3fff_ffff / MAP_X as i64;
+
+    let (dx, dy, dz) = (
+        (end.x - start.x) as i64,
+        (end.y - start.y) as i64,
+        (end.z - start.z) as i64,
+    );
+    let step = |d: i64| if d < 0 { -1 } else { 1 };
+    let (ixi, iyi, izi) = (step(dx), step(dy), step(dz));
+    let increment = |major: i64, d: i64| {
+        if d == 0 {
+            NO_STEP
+        } else {
+            (major * 1024 / d).abs()
+        }
+    };
+
+    let (dxi, dyi, dzi) = if dx.abs() >= dy.abs() && dx.abs() >= dz.abs() {
+        (1024, increment(dx, dy), increment(dx, dz))
+    } else if dy.abs() >= dz.abs() {
+        (increment(dy, dx), 1024, increment(dy, dz))
+    } else {
+        (increment(dz, dx), increment(dz, dy), 1024)
+    };
+
+    let start_error = |i: i64, di: i64| if i >= 0 { di - di / 2 } else { di / 2 };
+    let (mut ex, mut ey, mut ez) = (
+        start_error(ixi, dxi),
+        start_error(iyi, dyi),
+        start_error(izi, dzi),
+    );
+
+    let mut current = start;
+    let mut blocks = Vec::new();
+
+    loop {
+        blocks.push(current);
+
+        if blocks.len() == MAX_BLOCK_LINE_LENGTH || current == end {
+            break;
+        }
+
+        if ez <= ex && ez <= ey {
+            current.z += izi as i32;
+            if current.z < 0 || current.z >= MAP_Z {
+                break;
+            }
+            ez += dzi;
+        } else if ex < ey {
+            current.x += ixi as i32;
+            if current.x < 0 || current.x >= MAP_X {
+                break;
+            }
+            ex += dxi;
+        } else {
+            current.y += iyi as i32;
+            if current.y < 0 || current.y >= MAP_Y {
+                break;
+            }
+            ey += dyi;
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grenade_destroy() {
+        let mut world = World::new();
+        let center = BlockPosition::new_xyz(100, 100, 30);
+
+        for z in 29..=31 {
+            world.set(BlockPosition::new_xyz(100, 100, z), Some(DEFAULT_COLOR));
+        }
+        world.set(center.offset(2, 0, 0), Some(DEFAULT_COLOR));
+
+        let action = BlockAction {
+            player_id: PlayerId(0),
+            kind: ActionKind::GDestroy,
+            position: center,
+        };
+        let changes = world.apply_block_action(&action, DEFAULT_COLOR);
+
+        assert_eq!(changes.len(), 3);
+        assert!(!world.is_solid(center));
+        assert!(world.is_solid(center.offset(2, 0, 0)));
+    }
+
+    #[test]
+    fn floating_pillar() {
+        let mut world = World::new();
+
+        for z in 50..MAP_Z {
+            world.set(BlockPosition::new_xyz(10, 10, z), Some(DEFAULT_COLOR));
+        }
+
+        let action = BlockAction {
+            player_id: PlayerId(0),
+            kind: ActionKind::BSLDestroy,
+            position: BlockPosition::new_xyz(10, 10, 55),
+        };
+        let changes = world.update(&Msg::BlockAction(action), |_| None);
+
+        assert_eq!(changes.len(), 6);
+        assert!(!world.is_solid(BlockPosition::new_xyz(10, 10, 50)));
+        assert!(world.is_grounded(BlockPosition::new_xyz(10, 10, 56)));
+    }
+
+    #[test]
+    fn cube_line() {
+        let line = block_line(
+            BlockPosition::new_xyz(0, 0, 0),
+            BlockPosition::new_xyz(2, 1, 0),
+        );
+
+        assert_eq!(
+            line,
+            [
+                BlockPosition::new_xyz(0, 0, 0),
+                BlockPosition::new_xyz(1, 0, 0),
+                BlockPosition::new_xyz(1, 1, 0),
+                BlockPosition::new_xyz(2, 1, 0),
+            ]
+        );
+
+        // Reference lines from the `cubeline` of voxlap.
+        let references = [
+            // Negative x and y.
+            (
+                BlockPosition::new_xyz(10, 10, 30),
+                BlockPosition::new_xyz(7, 8, 30),
+                vec![
+                    BlockPosition::new_xyz(10, 10, 30),
+                    BlockPosition::new_xyz(9, 10, 30),
+                    BlockPosition::new_xyz(9, 9, 30),
+                    BlockPosition::new_xyz(8, 9, 30),
+                    BlockPosition::new_xyz(8, 8, 30),
+                    BlockPosition::new_xyz(7, 8, 30),
+                ],
+            ),
+            // Z-major, going up.
+            (
+                BlockPosition::new_xyz(5, 5, 40),
+                BlockPosition::new_xyz(6, 6, 34),
+                vec![
+                    BlockPosition::new_xyz(5, 5, 40),
+                    BlockPosition::new_xyz(5, 5, 39),
+                    BlockPosition::new_xyz(5, 5, 38),
+                    BlockPosition::new_xyz(5, 5, 37),
+                    BlockPosition::new_xyz(5, 6, 37),
+                    BlockPosition::new_xyz(6, 6, 37),
+                    BlockPosition::new_xyz(6, 6, 36),
+                    BlockPosition::new_xyz(6, 6, 35),
+                    BlockPosition::new_xyz(6, 6, 34),
+                ],
+            ),
+            // Diagonal.
+            (
+                BlockPosition::new_xyz(20, 20, 20),
+                BlockPosition::new_xyz(23, 23, 23),
+                vec![
+                    BlockPosition::new_xyz(20, 20, 20),
+                    BlockPosition::new_xyz(20, 20, 21),
+                    BlockPosition::new_xyz(20, 21, 21),
+                    BlockPosition::new_xyz(21, 21, 21),
+                    BlockPosition::new_xyz(21, 21, 22),
+                    BlockPosition::new_xyz(21, 22, 22),
+                    BlockPosition::new_xyz(22, 22, 22),
+                    BlockPosition::new_xyz(22, 22, 23),
+                    BlockPosition::new_xyz(22, 23, 23),
+                    BlockPosition::new_xyz(23, 23, 23),
+                ],
+            ),
+            // Negative diagonal.
+            (
+                BlockPosition::new_xyz(3, 3, 10),
+                BlockPosition::new_xyz(0, 0, 7),
+                vec![
+                    BlockPosition::new_xyz(3, 3, 10),
+                    BlockPosition::new_xyz(3, 3, 9),
+                    BlockPosition::new_xyz(3, 2, 9),
+                    BlockPosition::new_xyz(2, 2, 9),
+                    BlockPosition::new_xyz(2, 2, 8),
+                    BlockPosition::new_xyz(2, 1, 8),
+                    BlockPosition::new_xyz(1, 1, 8),
+                    BlockPosition::new_xyz(1, 1, 7),
+                    BlockPosition::new_xyz(1, 0, 7),
+                    BlockPosition::new_xyz(0, 0, 7),
+                ],
+            ),
+        ];
+        for (start, end, expected) in references {
+            assert_eq!(block_line(start, end), expected);
+        }
+
+        // Long lines are cut.
+        let line = block_line(
+            BlockPosition::new_xyz(0, 0, 5),
+            BlockPosition::new_xyz(100, 0, 5),
+        );
+        assert_eq!(line.len(), MAX_BLOCK_LINE_LENGTH);
+        assert_eq!(line.last(), Some(&BlockPosition::new_xyz(49, 0, 5)));
+    }
+
+    #[test]
+    fn vxl_column() {
+        // Every column: air down to 62, then two colored voxels.
+        let column = [0, 62, 63, 0, 1, 2, 3, 0, 4, 5, 6, 0];
+        let data = column.repeat((MAP_X * MAP_Y) as usize);
+
+        let world = World::from_vxl(&data).unwrap();
+        let position = BlockPosition::new_xyz(7, 9, 63);
+
+        assert!(!world.is_solid(position.offset(0, 0, -2)));
+        assert_eq!(world.color(position), Some(Color::new_rgb(6, 5, 4)));
+    }
+
+    #[test]
+    fn vxl_invalid_spans() {
+        // The top colors end before they start.
+        assert_eq!(
+            World::from_vxl(&[0, 10, 5, 0]),
+            Err(MapError::InvalidSpan { x: 0, y: 0 })
+        );
+
+        // More top colors than the span has.
+        let mut data = vec![2, 10, 12, 0];
+        data.extend([0; 16]);
+        assert_eq!(
+            World::from_vxl(&data),
+            Err(MapError::InvalidSpan { x: 0, y: 0 })
+        );
+    }
+
+    #[test]
+    fn vxl_roundtrip() {
+        let column = [0, 62, 63, 0, 1, 2, 3, 0, 4, 5, 6, 0];
+        let data = column.repeat((MAP_X * MAP_Y) as usize);
+
+        // A floating block and a cave below the surface.
+        let mut world = World::from_vxl(&data).unwrap();
+        let red = Color::new_rgb(255, 0, 0);
+        world.set(BlockPosition::new_xyz(3, 4, 20), Some(red));
+        for z in 40..62 {
+            world.set(BlockPosition::new_xyz(5, 5, z), Some(red));
+        }
+        world.set(BlockPosition::new_xyz(5, 5, 50), None);
+
+        let vxl = world.to_vxl();
+        let loaded = World::from_vxl(&vxl).unwrap();
+
+        assert_eq!(loaded.to_vxl(), vxl);
+        assert_eq!(
+            loaded.color(BlockPosition::new_xyz(0, 0, 62)),
+            Some(Color::new_rgb(3, 2, 1))
+        );
+        assert_eq!(loaded.color(BlockPosition::new_xyz(3, 4, 20)), Some(red));
+        assert!(!loaded.is_solid(BlockPosition::new_xyz(3, 4, 21)));
+        assert!(!loaded.is_solid(BlockPosition::new_xyz(5, 5, 50)));
+        assert_eq!(loaded.color(BlockPosition::new_xyz(5, 5, 51)), Some(red));
+        assert!(loaded.is_solid(BlockPosition::new_xyz(5, 5, 45)));
+    }
+}